@@ -1,40 +1,167 @@
+use std::collections::HashSet;
+use std::env;
 use std::ffi::OsStr;
+use std::path::PathBuf;
 use std::process;
 
 use failure::Error;
 use semver::Version;
+use serde_json::Value;
 
 #[derive(Deserialize)]
 pub struct CargoProject {
     pub packages: Vec<CargoPackage>,
+    pub workspace_members: Vec<String>,
+    pub workspace_root: PathBuf,
+    pub resolve: Option<CargoResolve>,
 }
 
 #[derive(Deserialize)]
 pub struct CargoPackage {
+    pub id: String,
     pub name: String,
     pub version: Version,
+    pub links: Option<String>,
+    pub manifest_path: PathBuf,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+    #[serde(default)]
+    pub targets: Vec<CargoTarget>,
+}
+
+#[derive(Deserialize)]
+pub struct CargoTarget {
+    pub kind: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CargoResolve {
+    pub nodes: Vec<CargoResolveNode>,
+}
+
+#[derive(Deserialize)]
+pub struct CargoResolveNode {
+    pub id: String,
+    #[serde(default)]
+    pub deps: Vec<CargoResolveDep>,
+}
+
+#[derive(Deserialize)]
+pub struct CargoResolveDep {
+    pub pkg: String,
+    #[serde(default)]
+    pub dep_kinds: Vec<CargoDepKind>,
+}
+
+#[derive(Deserialize)]
+pub struct CargoDepKind {
+    pub kind: Option<String>,
+}
+
+impl CargoProject {
+    /// Restricts the packages to those that are compiled for the target,
+    /// by walking the resolve graph from the workspace members while
+    /// skipping build dependencies and proc macros (which are compiled
+    /// for the host).
+    fn retain_target_packages(&mut self) {
+        let resolve = match self.resolve {
+            Some(ref resolve) => resolve,
+            None => return,
+        };
+
+        let proc_macros: HashSet<&str> = self.packages
+            .iter()
+            .filter(|pkg| pkg.is_proc_macro())
+            .map(|pkg| pkg.id.as_str())
+            .collect();
+
+        let mut reachable = HashSet::new();
+        let mut queue = self.workspace_members.clone();
+
+        while let Some(id) = queue.pop() {
+            if proc_macros.contains(id.as_str()) || !reachable.insert(id.clone()) {
+                continue;
+            }
+            let is_member = self.workspace_members.contains(&id);
+            if let Some(node) = resolve.nodes.iter().find(|node| node.id == id) {
+                for dep in node.deps.iter() {
+                    let is_target_dep = dep.dep_kinds.is_empty()
+                        || dep.dep_kinds.iter().any(|dep_kind| match dep_kind.kind {
+                            None => true,
+                            Some(ref kind) => kind == "dev" && is_member,
+                        });
+                    if is_target_dep {
+                        queue.push(dep.pkg.clone());
+                    }
+                }
+            }
+        }
+
+        self.packages.retain(|pkg| reachable.contains(&pkg.id));
+    }
+}
+
+impl CargoPackage {
+    fn is_proc_macro(&self) -> bool {
+        self.targets
+            .iter()
+            .any(|target| target.kind.iter().any(|kind| kind == "proc-macro"))
+    }
 }
 
 #[derive(StructOpt)]
 pub struct CargoOptions {
-    #[structopt(value_name = "TRIPLE", long = "target", help = "Build for the target triple")]
-    pub target: String,
+    #[structopt(
+        value_name = "TRIPLE",
+        long = "target",
+        help = "Build for the target triple (may be repeated or comma-separated, defaults to Cross.toml)",
+        raw(require_delimiter = "true")
+    )]
+    pub targets: Vec<String>,
     #[structopt(value_name = "SPEC", short = "p", long = "package", help = "Package to build")]
     pub package: Option<String>,
     #[structopt(long = "all", help = "Build all packages in the workspace")]
     pub all: bool,
+    #[structopt(
+        value_name = "SPEC",
+        long = "exclude",
+        help = "Exclude packages from the build",
+        raw(number_of_values = "1")
+    )]
+    pub exclude: Vec<String>,
     #[structopt(long = "lib", help = "Build only this package's library")]
     pub lib: bool,
     #[structopt(value_name = "NAME", long = "bin", help = "Build only the specified binary")]
     pub bin: Option<String>,
-    #[structopt(long = "libs", help = "Build all binaries")]
+    #[structopt(long = "bins", help = "Build all binaries")]
     pub bins: bool,
     #[structopt(value_name = "NAME", long = "example", help = "Build only the specified example")]
     pub example: Option<String>,
     #[structopt(long = "examples", help = "Build all examples")]
     pub examples: bool,
+    #[structopt(value_name = "NAME", long = "test", help = "Build only the specified test target")]
+    pub test: Option<String>,
+    #[structopt(value_name = "NAME", long = "bench", help = "Build only the specified bench target")]
+    pub bench: Option<String>,
     #[structopt(long = "release", help = "Build artifacts in release mode, with optimizations")]
     pub release: bool,
+    #[structopt(
+        value_name = "PROFILE-NAME",
+        long = "profile",
+        help = "Build artifacts with the specified profile"
+    )]
+    pub profile: Option<String>,
+    #[structopt(
+        value_name = "N",
+        short = "j",
+        long = "jobs",
+        help = "Number of parallel jobs, defaults to # of CPUs"
+    )]
+    pub jobs: Option<u32>,
+    #[structopt(value_name = "FMT", long = "message-format", help = "Error format")]
+    pub message_format: Option<String>,
+    #[structopt(flatten)]
+    pub manifest: ManifestOptions,
     #[structopt(
         short = "v",
         long = "verbose",
@@ -42,35 +169,116 @@ pub struct CargoOptions {
         parse(from_occurrences)
     )]
     pub verbose: u64,
+    #[structopt(
+        value_name = "ARGS",
+        raw(last = "true"),
+        help = "Additional arguments to pass through"
+    )]
+    pub args: Vec<String>,
+}
+
+/// Options that affect how cargo resolves the project, shared between
+/// the build commands and `cargo metadata`.
+#[derive(StructOpt)]
+pub struct ManifestOptions {
+    #[structopt(
+        value_name = "FEATURES",
+        long = "features",
+        help = "Space-separated list of features to activate",
+        raw(number_of_values = "1")
+    )]
+    pub features: Vec<String>,
+    #[structopt(long = "all-features", help = "Activate all available features")]
+    pub all_features: bool,
+    #[structopt(long = "no-default-features", help = "Do not activate the `default` feature")]
+    pub no_default_features: bool,
+    #[structopt(value_name = "PATH", long = "manifest-path", help = "Path to Cargo.toml")]
+    pub manifest_path: Option<String>,
+    #[structopt(long = "locked", help = "Require Cargo.lock is up to date")]
+    pub locked: bool,
+    #[structopt(long = "frozen", help = "Require Cargo.lock and cache are up to date")]
+    pub frozen: bool,
+    #[structopt(long = "offline", help = "Run without accessing the network")]
+    pub offline: bool,
+}
+
+#[derive(StructOpt)]
+pub struct TestOptions {
+    #[structopt(flatten)]
+    pub cargo: CargoOptions,
+    #[structopt(
+        value_name = "RUNNER",
+        long = "runner",
+        help = "Emulator used to run the test binaries (defaults to qemu for foreign targets)"
+    )]
+    pub runner: Option<String>,
+    #[structopt(long = "no-run", help = "Compile, but don't run tests")]
+    pub no_run: bool,
+}
+
+#[derive(StructOpt)]
+pub struct RunOptions {
+    #[structopt(flatten)]
+    pub cargo: CargoOptions,
+    #[structopt(
+        value_name = "RUNNER",
+        long = "runner",
+        help = "Emulator used to run the binary (defaults to qemu for foreign targets)"
+    )]
+    pub runner: Option<String>,
 }
 
 impl CargoOptions {
-    fn apply_all(&self, command: &mut process::Command) {
-        command.args(&["--target", &self.target]);
+    fn apply_all(&self, target: &str, command: &mut process::Command) {
+        self.apply_flags(target, command);
+        command.args(&self.args);
+    }
+
+    fn apply_flags(&self, target: &str, command: &mut process::Command) {
+        command.args(["--target", target]);
         if let Some(ref package) = self.package {
-            command.args(&["--package", package]);
+            command.args(["--package", package]);
         }
         if self.all {
             command.arg("--all");
         }
+        for exclude in self.exclude.iter() {
+            command.args(["--exclude", exclude]);
+        }
         if self.lib {
             command.arg("--lib");
         }
         if let Some(ref bin) = self.bin {
-            command.args(&["--bin", bin]);
+            command.args(["--bin", bin]);
         }
         if self.bins {
             command.arg("--bins");
         }
         if let Some(ref example) = self.example {
-            command.args(&["--example", example]);
+            command.args(["--example", example]);
         }
         if self.examples {
             command.arg("--examples");
         }
+        if let Some(ref test) = self.test {
+            command.args(["--test", test]);
+        }
+        if let Some(ref bench) = self.bench {
+            command.args(["--bench", bench]);
+        }
         if self.release {
             command.arg("--release");
         }
+        if let Some(ref profile) = self.profile {
+            command.args(["--profile", profile]);
+        }
+        if let Some(jobs) = self.jobs {
+            command.args(["--jobs", &jobs.to_string()]);
+        }
+        if let Some(ref message_format) = self.message_format {
+            command.args(["--message-format", message_format]);
+        }
+        self.manifest.apply_all(command);
         self.apply_verbose(command);
     }
 
@@ -83,22 +291,169 @@ impl CargoOptions {
     }
 }
 
-pub fn build<I, K, V>(opts: &CargoOptions, envs: I) -> Result<process::ExitStatus, Error>
+impl ManifestOptions {
+    /// Returns whether the network must not be accessed.
+    pub fn is_offline(&self) -> bool {
+        self.offline || self.frozen
+    }
+
+    fn apply_all(&self, command: &mut process::Command) {
+        for features in self.features.iter() {
+            command.args(["--features", features]);
+        }
+        if self.all_features {
+            command.arg("--all-features");
+        }
+        if self.no_default_features {
+            command.arg("--no-default-features");
+        }
+        if let Some(ref manifest_path) = self.manifest_path {
+            command.args(["--manifest-path", manifest_path]);
+        }
+        if self.locked {
+            command.arg("--locked");
+        }
+        if self.frozen {
+            command.arg("--frozen");
+        }
+        if self.offline {
+            command.arg("--offline");
+        }
+    }
+}
+
+impl TestOptions {
+    fn apply_all(&self, target: &str, command: &mut process::Command) {
+        self.cargo.apply_flags(target, command);
+        if self.no_run {
+            command.arg("--no-run");
+        }
+        if !self.cargo.args.is_empty() {
+            command.arg("--");
+            command.args(&self.cargo.args);
+        }
+    }
+}
+
+impl RunOptions {
+    fn apply_all(&self, target: &str, command: &mut process::Command) {
+        self.cargo.apply_flags(target, command);
+        if !self.cargo.args.is_empty() {
+            command.arg("--");
+            command.args(&self.cargo.args);
+        }
+    }
+}
+
+pub fn build<I, K, V>(
+    opts: &CargoOptions,
+    target: &str,
+    envs: I,
+) -> Result<process::ExitStatus, Error>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    execute("build", opts, target, envs)
+}
+
+pub fn check<I, K, V>(
+    opts: &CargoOptions,
+    target: &str,
+    envs: I,
+) -> Result<process::ExitStatus, Error>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    execute("check", opts, target, envs)
+}
+
+pub fn test<I, K, V>(
+    opts: &TestOptions,
+    target: &str,
+    envs: I,
+) -> Result<process::ExitStatus, Error>
 where
     I: IntoIterator<Item = (K, V)>,
     K: AsRef<OsStr>,
     V: AsRef<OsStr>,
 {
     let mut command = process::Command::new("cargo");
-    command.arg("build");
-    opts.apply_all(&mut command);
+    command.arg("test");
+    opts.apply_all(target, &mut command);
     command.envs(envs);
     Ok(command.status()?)
 }
 
-pub fn metadata(_opts: &CargoOptions) -> Result<CargoProject, Error> {
+pub fn run<I, K, V>(opts: &RunOptions, target: &str, envs: I) -> Result<process::ExitStatus, Error>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
     let mut command = process::Command::new("cargo");
-    command.args(&["metadata", "-q", "--format-version", "1"]);
+    command.arg("run");
+    opts.apply_all(target, &mut command);
+    command.envs(envs);
+    Ok(command.status()?)
+}
+
+fn execute<I, K, V>(
+    subcommand: &str,
+    opts: &CargoOptions,
+    target: &str,
+    envs: I,
+) -> Result<process::ExitStatus, Error>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    let mut command = process::Command::new("cargo");
+    command.arg(subcommand);
+    opts.apply_all(target, &mut command);
+    command.envs(envs);
+    Ok(command.status()?)
+}
+
+pub fn metadata(opts: &ManifestOptions, target: &str) -> Result<CargoProject, Error> {
+    let mut command = process::Command::new("cargo");
+    command.args(["metadata", "-q", "--format-version", "1"]);
+    command.args(["--filter-platform", target]);
+    opts.apply_all(&mut command);
+
+    let output = command.output()?;
+
+    if !output.status.success() {
+        bail!("Could not retrieve project metadata.");
+    }
+
+    let mut project: CargoProject = ::serde_json::from_slice(&output.stdout)?;
+    project.retain_target_packages();
+
+    Ok(project)
+}
+
+/// Returns whether the options refer to a cargo project, i.e. a manifest
+/// path was given or there is a `Cargo.toml` in the current directory or
+/// one of its parents.
+pub fn has_manifest(opts: &ManifestOptions) -> bool {
+    if opts.manifest_path.is_some() {
+        return true;
+    }
+    env::current_dir()
+        .map(|dir| dir.ancestors().any(|dir| dir.join("Cargo.toml").is_file()))
+        .unwrap_or(false)
+}
+
+/// Retrieves the workspace metadata without resolving dependencies.
+pub fn workspace(opts: &ManifestOptions) -> Result<CargoProject, Error> {
+    let mut command = process::Command::new("cargo");
+    command.args(["metadata", "-q", "--format-version", "1", "--no-deps"]);
+    opts.apply_all(&mut command);
 
     let output = command.output()?;
 
@@ -108,3 +463,79 @@ pub fn metadata(_opts: &CargoOptions) -> Result<CargoProject, Error> {
 
     Ok(::serde_json::from_slice(&output.stdout)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, kind: &str) -> String {
+        format!(
+            r#"{{"id": "{0}", "name": "{0}", "version": "1.0.0", "links": null,
+                "manifest_path": "/{0}/Cargo.toml", "targets": [{{"kind": ["{1}"]}}]}}"#,
+            name, kind
+        )
+    }
+
+    fn project(resolve: &str) -> CargoProject {
+        let packages: Vec<String> = [
+            ("app", "bin"),
+            ("lib", "lib"),
+            ("legacy", "lib"),
+            ("shared", "lib"),
+            ("build-helper", "lib"),
+            ("dev-helper", "lib"),
+            ("lib-dev", "lib"),
+            ("macro", "proc-macro"),
+            ("macro-dep", "lib"),
+        ].iter()
+            .map(|&(name, kind)| package(name, kind))
+            .collect();
+        let source = format!(
+            r#"{{"packages": [{}], "workspace_members": ["app"], "workspace_root": "/",
+                "resolve": {}}}"#,
+            packages.join(", "),
+            resolve
+        );
+        serde_json::from_str(&source).unwrap()
+    }
+
+    fn package_names(project: &CargoProject) -> Vec<&str> {
+        let mut names: Vec<&str> = project.packages.iter().map(|pkg| pkg.name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn retains_packages_compiled_for_the_target() {
+        let mut project = project(
+            r#"{"nodes": [
+                {"id": "app", "deps": [
+                    {"pkg": "lib", "dep_kinds": [{"kind": null}]},
+                    {"pkg": "shared", "dep_kinds": [{"kind": "build"}, {"kind": null}]},
+                    {"pkg": "build-helper", "dep_kinds": [{"kind": "build"}]},
+                    {"pkg": "dev-helper", "dep_kinds": [{"kind": "dev"}]},
+                    {"pkg": "macro", "dep_kinds": [{"kind": null}]}
+                ]},
+                {"id": "lib", "deps": [
+                    {"pkg": "legacy"},
+                    {"pkg": "lib-dev", "dep_kinds": [{"kind": "dev"}]}
+                ]},
+                {"id": "macro", "deps": [{"pkg": "macro-dep"}]}
+            ]}"#,
+        );
+        project.retain_target_packages();
+
+        assert_eq!(
+            package_names(&project),
+            ["app", "dev-helper", "legacy", "lib", "shared"]
+        );
+    }
+
+    #[test]
+    fn keeps_all_packages_without_resolve() {
+        let mut project = project("null");
+        project.retain_target_packages();
+
+        assert_eq!(project.packages.len(), 9);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use console::style;
+use directories::ProjectDirs;
+use failure::Error;
+use indicatif::HumanBytes;
+
+use toolchains::ToolchainManager;
+use utils::du::disk_usage;
+
+#[derive(StructOpt)]
+pub enum CacheCommand {
+    #[structopt(
+        name = "gc",
+        about = "Remove packages no longer referenced by the toolchain registry",
+        author = "",
+        version = ""
+    )]
+    Gc(GcOptions),
+    #[structopt(
+        name = "du",
+        about = "Report disk usage per target and package",
+        author = "",
+        version = ""
+    )]
+    Du,
+}
+
+#[derive(StructOpt)]
+pub struct GcOptions {
+    #[structopt(long = "dry-run", help = "Only print what would be removed")]
+    pub dry_run: bool,
+}
+
+pub fn command(dirs: ProjectDirs, cmd: CacheCommand) -> Result<(), Error> {
+    match cmd {
+        CacheCommand::Gc(opts) => command_gc(dirs, opts),
+        CacheCommand::Du => command_du(dirs),
+    }
+}
+
+fn command_gc(dirs: ProjectDirs, opts: GcOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, false)?;
+
+    let mut freed = 0;
+    for package in manager.get_installed_packages()? {
+        if package.description.is_some() {
+            continue;
+        }
+        let size = disk_usage(&package.path)?;
+        eprintln!(
+            "{:>12} {} ({})",
+            style("Removing").green().bold(),
+            package.path.display(),
+            HumanBytes(size)
+        );
+        if !opts.dry_run {
+            fs::remove_dir_all(&package.path)?;
+        }
+        freed += size;
+    }
+
+    eprintln!(
+        "{:>12} {}{}",
+        style("Freed").green().bold(),
+        HumanBytes(freed),
+        if opts.dry_run { " (dry run)" } else { "" }
+    );
+
+    Ok(())
+}
+
+fn command_du(dirs: ProjectDirs) -> Result<(), Error> {
+    // only reports on disk usage, for which the cached registry will do
+    let manager = ToolchainManager::new(&dirs, true)?;
+
+    let mut targets = BTreeMap::new();
+    for package in manager.get_installed_packages()? {
+        let size = disk_usage(&package.path)?;
+        let description = match package.description {
+            Some(description) => description,
+            None => {
+                let checksum = package.path.file_name().unwrap_or_default();
+                format!("unreferenced {}", checksum.to_string_lossy())
+            }
+        };
+        targets
+            .entry(package.target)
+            .or_insert_with(Vec::new)
+            .push((description, size));
+    }
+
+    let mut total = 0;
+    for (target, packages) in targets {
+        let target_size: u64 = packages.iter().map(|&(_, size)| size).sum();
+        println!("{} ({})", style(target).bold(), HumanBytes(target_size));
+        for (description, size) in packages {
+            println!("    {} ({})", description, HumanBytes(size));
+        }
+        total += target_size;
+    }
+    println!("Total {}", HumanBytes(total));
+
+    Ok(())
+}
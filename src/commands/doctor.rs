@@ -0,0 +1,213 @@
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use console::style;
+use directories::ProjectDirs;
+use failure::Error;
+use heck::ShoutySnakeCase;
+use platforms;
+
+use toolchains::ToolchainManager;
+
+#[derive(StructOpt)]
+pub struct DoctorOptions {
+    #[structopt(
+        value_name = "TRIPLE",
+        long = "target",
+        help = "Diagnose the setup for the target triple"
+    )]
+    pub target: String,
+}
+
+/// Host-wide flags that build scripts append to the target-scoped ones when
+/// compiling for the target. Host-wide tools are fine, as the target-scoped
+/// variables take precedence over them.
+static CONFLICTING_ENV_VARS: &[&str] = &["CFLAGS", "CXXFLAGS", "LDFLAGS"];
+
+struct Report {
+    problems: usize,
+}
+
+impl Report {
+    fn ok(&mut self, message: &str) {
+        eprintln!("{:>12} {}", style("ok").green().bold(), message);
+    }
+
+    fn warn(&mut self, message: &str, hint: &str) {
+        eprintln!("{:>12} {}", style("warning").yellow().bold(), message);
+        eprintln!("{:>12} {}", style("hint").cyan(), hint);
+    }
+
+    fn fail(&mut self, message: &str, hint: &str) {
+        self.problems += 1;
+        eprintln!("{:>12} {}", style("error").red().bold(), message);
+        eprintln!("{:>12} {}", style("hint").cyan(), hint);
+    }
+}
+
+pub fn command(dirs: ProjectDirs, opts: DoctorOptions) -> Result<(), Error> {
+    let target = opts.target.as_str();
+    let mut report = Report { problems: 0 };
+
+    match platforms::guess_current() {
+        Some(host) => report.ok(&format!("host detected as {}", host.target_triple)),
+        None => {
+            report.fail(
+                "could not detect the host platform",
+                "cargo-cross only supports the hosts listed in the README",
+            );
+            process::exit(1);
+        }
+    }
+
+    match rust_target_installed(target) {
+        Ok(true) => report.ok(&format!("Rust standard library for {} is installed", target)),
+        Ok(false) => report.fail(
+            &format!("Rust standard library for {} is not installed", target),
+            &format!("run `rustup target add {}`", target),
+        ),
+        Err(err) => report.fail(
+            &format!("could not query the active Rust toolchain ({})", err),
+            "make sure `rustc` is on your PATH",
+        ),
+    }
+
+    let manager = ToolchainManager::new(&dirs, false)?;
+
+    if !manager.is_toolchain_base_available(target) {
+        report.fail(
+            &format!(
+                "no toolchain available for target {} on host {}",
+                target,
+                manager.host()
+            ),
+            "run `cargo cross toolchain list` to see the supported targets",
+        );
+    } else if !manager.is_toolchain_base_installed(target) {
+        report.ok(&format!("toolchain for {} is available", target));
+        report.fail(
+            &format!("toolchain for {} is not installed", target),
+            &format!("run `cargo cross toolchain install --target {}`", target),
+        );
+    } else {
+        report.ok(&format!("toolchain for {} is available", target));
+        report.ok(&format!("toolchain for {} is installed", target));
+        check_compiler(&mut report, &manager, target);
+    }
+
+    let features = manager.get_toolchain_features(target);
+    for feature in features.iter() {
+        if feature.installed {
+            report.ok(&format!(
+                "support package {} {} is installed",
+                feature.crate_name, feature.crate_version_req
+            ));
+        }
+    }
+
+    let target_vars = [
+        format!("CC_{}", target.replace('-', "_")),
+        format!("CFLAGS_{}", target.replace('-', "_")),
+        format!("AR_{}", target.replace('-', "_")),
+        format!("RANLIB_{}", target.replace('-', "_")),
+        format!("CARGO_TARGET_{}_LINKER", target.to_shouty_snake_case()),
+    ];
+    for var in target_vars.iter() {
+        if env::var_os(var).is_some() {
+            report.warn(
+                &format!("{} is set and will be overridden by cargo-cross", var),
+                &format!("unset {} to avoid surprises outside of cargo-cross", var),
+            );
+        }
+    }
+    for var in CONFLICTING_ENV_VARS {
+        if env::var_os(var).is_some() {
+            report.fail(
+                &format!("{} is set and will leak into builds for {}", var, target),
+                &format!("unset {} when cross compiling", var),
+            );
+        }
+    }
+    for feature in features.iter() {
+        for var in feature.env_vars.iter() {
+            if env::var_os(var).is_some() {
+                report.fail(
+                    &format!(
+                        "{} is set and conflicts with the support package for {}",
+                        var, feature.crate_name
+                    ),
+                    &format!("unset {} so the cross build uses the support package", var),
+                );
+            }
+        }
+    }
+
+    finish(&report)
+}
+
+fn check_compiler(report: &mut Report, manager: &ToolchainManager, target: &str) {
+    let compiler = match manager.get_toolchain_compiler_path(target) {
+        Some(compiler) => compiler,
+        None => {
+            report.fail(
+                &format!("no compiler found for the toolchain for {}", target),
+                "install clang and lld, or set `clang` in the cargo-cross config.toml",
+            );
+            return;
+        }
+    };
+
+    let reinstall = format!(
+        "run `cargo cross toolchain remove {0}` and `cargo cross toolchain install --target {0}`",
+        target
+    );
+
+    if !compiler.exists() {
+        report.fail(
+            &format!("compiler {} is missing", compiler.display()),
+            &reinstall,
+        );
+        return;
+    }
+
+    match process::Command::new(&compiler).arg("--version").output() {
+        Ok(ref output) if output.status.success() => {
+            report.ok(&format!("compiler {} runs", compiler.display()))
+        }
+        Ok(output) => report.fail(
+            &format!(
+                "compiler {} failed with {}",
+                compiler.display(),
+                output.status
+            ),
+            &reinstall,
+        ),
+        Err(err) => report.fail(
+            &format!("compiler {} could not be run ({})", compiler.display(), err),
+            &reinstall,
+        ),
+    }
+}
+
+fn rust_target_installed(target: &str) -> Result<bool, Error> {
+    let output = process::Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .output()?;
+    if !output.status.success() {
+        bail!("rustc exited with {}", output.status);
+    }
+
+    let sysroot = PathBuf::from(String::from_utf8(output.stdout)?.trim());
+    let path = sysroot.join("lib").join("rustlib").join(target).join("lib");
+
+    Ok(path.exists())
+}
+
+fn finish(report: &Report) -> Result<(), Error> {
+    if report.problems > 0 {
+        bail!("Found {} problem(s).", report.problems);
+    }
+    eprintln!("{:>12} no problems found", style("Finished").green().bold());
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+
+use directories::ProjectDirs;
+use failure::Error;
+use serde_json;
+
+use super::prepare_environment;
+use cargo::ManifestOptions;
+use toolchains::ToolchainManager;
+
+#[derive(StructOpt)]
+pub struct EnvOptions {
+    #[structopt(
+        value_name = "TRIPLE",
+        long = "target",
+        help = "Print the environment for the target triple"
+    )]
+    pub target: String,
+    #[structopt(
+        value_name = "FORMAT",
+        long = "format",
+        default_value = "bash",
+        help = "Output format",
+        raw(possible_values = r#"&["bash", "zsh", "fish", "powershell", "dotenv", "json"]"#)
+    )]
+    pub format: String,
+    #[structopt(flatten)]
+    pub manifest: ManifestOptions,
+}
+
+pub fn command(dirs: ProjectDirs, opts: EnvOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, opts.manifest.is_offline())?;
+    let env = prepare_environment(&manager, &opts.manifest, &opts.target)?;
+
+    let env: Vec<(String, String)> = env
+        .into_iter()
+        .map(|(key, value)| (key, value.to_string_lossy().into_owned()))
+        .collect();
+
+    if opts.format == "json" {
+        let map: BTreeMap<_, _> = env.into_iter().collect();
+        println!("{}", serde_json::to_string_pretty(&map)?);
+        return Ok(());
+    }
+
+    for (key, value) in env {
+        let line = match opts.format.as_str() {
+            "fish" => format!("set -gx {} {};", key, quote_fish(&value)),
+            "powershell" => format!("$env:{} = {}", key, quote_powershell(&value)),
+            "dotenv" => format!("{}={}", key, quote_dotenv(&value)),
+            _ => format!("export {}={};", key, quote_posix(&value)),
+        };
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+fn quote_posix(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn quote_fish(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn quote_powershell(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn quote_dotenv(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,96 @@
+use std::env;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+
+use directories::ProjectDirs;
+use failure::Error;
+use tempfile::NamedTempFile;
+
+use super::prepare_environment;
+use cargo::ManifestOptions;
+use toolchains::ToolchainManager;
+
+#[derive(StructOpt)]
+pub struct ExecOptions {
+    #[structopt(
+        value_name = "TRIPLE",
+        long = "target",
+        help = "Run in the environment for the target triple"
+    )]
+    pub target: String,
+    #[structopt(flatten)]
+    pub manifest: ManifestOptions,
+    #[structopt(
+        value_name = "COMMAND",
+        help = "Command to run, followed by its arguments",
+        raw(last = "true", required = "true")
+    )]
+    pub command: Vec<String>,
+}
+
+#[derive(StructOpt)]
+pub struct ShellOptions {
+    #[structopt(
+        value_name = "TRIPLE",
+        long = "target",
+        help = "Open a shell for the target triple"
+    )]
+    pub target: String,
+    #[structopt(flatten)]
+    pub manifest: ManifestOptions,
+}
+
+pub fn command_exec(dirs: ProjectDirs, opts: ExecOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, opts.manifest.is_offline())?;
+    let env = prepare_environment(&manager, &opts.manifest, &opts.target)?;
+
+    let mut command = process::Command::new(&opts.command[0]);
+    command.args(&opts.command[1..]);
+    command.envs(env);
+
+    let status = command
+        .status()
+        .map_err(|err| format_err!("Could not run {}: {}", opts.command[0], err))?;
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
+
+pub fn command_shell(dirs: ProjectDirs, opts: ShellOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, opts.manifest.is_offline())?;
+    let env = prepare_environment(&manager, &opts.manifest, &opts.target)?;
+
+    let shell = env::var_os("SHELL").unwrap_or_else(|| OsString::from("/bin/sh"));
+    let marker = format!("(cross:{}) ", opts.target);
+
+    let mut command = process::Command::new(&shell);
+    command.envs(env);
+    command.env("CARGO_CROSS_TARGET", &opts.target);
+
+    // bash resets PS1 from its rc files, so the marker is added by a
+    // wrapper rc file that sources the user's own one first
+    let mut rcfile = None;
+    if Path::new(&shell).file_name() == Some("bash".as_ref()) {
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "[ -f ~/.bashrc ] && . ~/.bashrc")?;
+        writeln!(file, "PS1='{}'\"$PS1\"", marker)?;
+        command.arg("--rcfile").arg(file.path()).arg("-i");
+        rcfile = Some(file);
+    } else {
+        let ps1 = env::var("PS1").unwrap_or_else(|_| "$ ".to_owned());
+        command.env("PS1", format!("{}{}", marker, ps1));
+    }
+
+    let status = command.status()?;
+    drop(rcfile);
+
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
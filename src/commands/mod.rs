@@ -0,0 +1,182 @@
+use std::ffi::OsString;
+
+use console::style;
+use failure::Error;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use cargo::{self, ManifestOptions};
+use config::ProjectConfig;
+use package::PackageInstall;
+use toolchains::ToolchainManager;
+use utils::progress::ProgressObserver;
+
+pub mod cache;
+pub mod doctor;
+pub mod env;
+pub mod exec;
+pub mod support;
+pub mod toolchain;
+
+pub fn install_toolchain_base(manager: &ToolchainManager, target: &str) -> Result<(), Error> {
+    let info = manager.get_toolchain_info(target).ok_or_else(|| {
+        format_err!(
+            "Cross compilation for target {} not supported.",
+            target
+        )
+    })?;
+
+    if !manager.is_toolchain_base_available(target) {
+        bail!(
+            "Could not find suitable toolchain for selected target ({}) and host ({}) system.",
+            target,
+            manager.host(),
+        );
+    }
+
+    eprintln!(
+        "{:>12} {} ({}{} {})",
+        style("Toolchain").magenta().bold(),
+        target,
+        if info.system { "system " } else { "" },
+        info.compiler,
+        info.version
+    );
+
+    if !manager.is_toolchain_base_installed(target) {
+        let install = manager.start_toolchain_base_installation(target)?;
+        package_install_progress(install)?;
+    }
+
+    Ok(())
+}
+
+pub fn prepare_toolchain(
+    manager: &ToolchainManager,
+    opts: &ManifestOptions,
+    config: &ProjectConfig,
+    target: &str,
+    unscoped: bool,
+) -> Result<Vec<(String, OsString)>, Error> {
+    install_toolchain_base(manager, target)?;
+
+    let mut metadata = cargo::metadata(opts, target)?;
+
+    // support packages disabled by the project are neither installed nor configured
+    metadata
+        .packages
+        .retain(|package| !config.is_feature_disabled(target, &package.name));
+
+    for package in metadata.packages.iter() {
+        if manager.is_toolchain_feature_available(target, package) {
+            eprintln!(
+                "{:>12} {} v{}",
+                style("Support").magenta().bold(),
+                package.name,
+                package.version
+            );
+            if !manager.is_toolchain_feature_installed(target, package) {
+                let install = manager.start_toolchain_feature_installation(target, package)?;
+                package_install_progress(install)?;
+            }
+        }
+    }
+
+    let mut env: Vec<_> = manager
+        .get_toolchain_environment(target, &metadata.packages, unscoped)?
+        .into_iter()
+        .collect();
+
+    for crate_name in config.enabled_features(target) {
+        let detected = metadata.packages.iter().any(|package| {
+            package.name == *crate_name && manager.is_toolchain_feature_available(target, package)
+        });
+        if detected {
+            continue;
+        }
+
+        let feature = manager
+            .get_toolchain_features(target)
+            .into_iter()
+            .find(|feature| feature.crate_name == *crate_name)
+            .ok_or_else(|| {
+                format_err!(
+                    "No support package for crate {} available for target {}.",
+                    crate_name,
+                    target
+                )
+            })?;
+        eprintln!(
+            "{:>12} {} {}",
+            style("Support").magenta().bold(),
+            feature.crate_name,
+            feature.crate_version_req
+        );
+        if !feature.installed {
+            let install = manager.start_named_toolchain_feature_installation(
+                target,
+                &feature.crate_name,
+                &feature.crate_version_req,
+            )?;
+            package_install_progress(install)?;
+        }
+        env.extend(manager.get_named_toolchain_feature_environment(
+            target,
+            &feature.crate_name,
+            &feature.crate_version_req,
+        )?);
+    }
+
+    env.extend(config.env(target));
+
+    Ok(env)
+}
+
+/// Prepares the environment for running arbitrary commands for the target,
+/// which also works outside of any cargo project (e.g. for a Makefile),
+/// where only the base toolchain is set up.
+pub fn prepare_environment(
+    manager: &ToolchainManager,
+    opts: &ManifestOptions,
+    target: &str,
+) -> Result<Vec<(String, OsString)>, Error> {
+    if !cargo::has_manifest(opts) {
+        install_toolchain_base(manager, target)?;
+        return Ok(manager
+            .get_toolchain_environment(target, &[], true)?
+            .into_iter()
+            .collect());
+    }
+    let config = ProjectConfig::load(opts)?;
+    prepare_toolchain(manager, opts, &config, target, true)
+}
+
+pub fn package_install_progress(install: PackageInstall) -> Result<(), Error> {
+    let progress_bar = ProgressBar::new(install.total());
+    progress_bar.set_draw_target(ProgressDrawTarget::stderr());
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg:>12.cyan.bold} {bytes} / {total_bytes} [{wide_bar}] {percent}%  ")
+            .progress_chars("=>-"),
+    );
+    progress_bar.set_message("Fetch");
+
+    struct ProgressBarObserver(ProgressBar);
+
+    impl ProgressObserver for ProgressBarObserver {
+        fn progress(&mut self, delta: u64) {
+            self.0.inc(delta);
+        }
+
+        fn complete(&mut self) {
+            self.0.finish_and_clear();
+        }
+
+        fn restart(&mut self) {
+            self.0.set_position(0);
+        }
+    }
+
+    install.perform(ProgressBarObserver(progress_bar))?;
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use console::style;
+use directories::ProjectDirs;
+use failure::Error;
+
+use cargo::{self, ManifestOptions};
+use config::ProjectConfig;
+use toolchains::ToolchainManager;
+
+#[derive(StructOpt)]
+pub struct SupportOptions {
+    #[structopt(
+        value_name = "TRIPLE",
+        long = "target",
+        help = "Report native dependencies for the target triple"
+    )]
+    pub target: String,
+    #[structopt(flatten)]
+    pub manifest: ManifestOptions,
+}
+
+pub fn command(dirs: ProjectDirs, opts: SupportOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, opts.manifest.is_offline())?;
+    let target = opts.target.as_str();
+
+    let config = ProjectConfig::load(&opts.manifest)?;
+    let metadata = cargo::metadata(&opts.manifest, target)?;
+
+    let mut supported = 0;
+    let mut unsupported = 0;
+
+    for package in metadata.packages.iter() {
+        let available = manager.is_toolchain_feature_available(target, package);
+        if available && config.is_feature_disabled(target, &package.name) {
+            unsupported += 1;
+            println!(
+                "{:>12} {} v{} (support package disabled by the project)",
+                style("Disabled").yellow().bold(),
+                package.name,
+                package.version
+            );
+        } else if available {
+            supported += 1;
+            println!(
+                "{:>12} {} v{}",
+                style("Support").green().bold(),
+                package.name,
+                package.version
+            );
+        } else if let Some(ref links) = package.links {
+            unsupported += 1;
+            println!(
+                "{:>12} {} v{} (links to native library `{}`)",
+                style("Missing").yellow().bold(),
+                package.name,
+                package.version,
+                links
+            );
+        } else if package.name.ends_with("-sys") {
+            unsupported += 1;
+            println!(
+                "{:>12} {} v{}",
+                style("Missing").yellow().bold(),
+                package.name,
+                package.version
+            );
+        }
+    }
+
+    // support packages the project enables even though no dependency
+    // requires them
+    let features = manager.get_toolchain_features(target);
+    for crate_name in config.enabled_features(target) {
+        let detected = metadata.packages.iter().any(|package| {
+            package.name == *crate_name && manager.is_toolchain_feature_available(target, package)
+        });
+        if detected {
+            continue;
+        }
+        match features.iter().find(|feature| feature.crate_name == *crate_name) {
+            Some(feature) => {
+                supported += 1;
+                println!(
+                    "{:>12} {} {} (enabled by the project)",
+                    style("Support").green().bold(),
+                    feature.crate_name,
+                    feature.crate_version_req
+                );
+            }
+            None => {
+                unsupported += 1;
+                println!(
+                    "{:>12} {} (enabled by the project, but not available)",
+                    style("Missing").yellow().bold(),
+                    crate_name
+                );
+            }
+        }
+    }
+
+    eprintln!(
+        "{:>12} {} with support packages, {} native dependencies without support for {}",
+        style("Summary").magenta().bold(),
+        supported,
+        unsupported,
+        target
+    );
+    if unsupported > 0 {
+        eprintln!(
+            "{:>12} crates without a support package need to build their native library \
+             from source, or will fail to build for {}",
+            style("Note").cyan(),
+            target
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,295 @@
+use std::path::PathBuf;
+
+use console::style;
+use directories::ProjectDirs;
+use failure::Error;
+use indicatif::HumanBytes;
+use serde_json;
+
+use super::{install_toolchain_base, package_install_progress};
+use toolchains::ToolchainManager;
+
+#[derive(StructOpt)]
+pub enum ToolchainCommand {
+    #[structopt(
+        name = "list",
+        about = "List the toolchains available for this host",
+        author = "",
+        version = ""
+    )]
+    List(ListOptions),
+    #[structopt(
+        name = "install",
+        about = "Install toolchains and support packages without building",
+        author = "",
+        version = ""
+    )]
+    Install(InstallOptions),
+    #[structopt(
+        name = "remove",
+        about = "Remove installed toolchains and their support packages",
+        author = "",
+        version = ""
+    )]
+    Remove(RemoveOptions),
+    #[structopt(
+        name = "import",
+        about = "Install toolchains and support packages from local archives",
+        author = "",
+        version = ""
+    )]
+    Import(ImportOptions),
+}
+
+#[derive(StructOpt)]
+pub struct ListOptions {
+    #[structopt(
+        value_name = "FORMAT",
+        long = "format",
+        default_value = "human",
+        help = "Output format",
+        raw(possible_values = r#"&["human", "json"]"#)
+    )]
+    pub format: String,
+}
+
+#[derive(StructOpt)]
+pub struct InstallOptions {
+    #[structopt(
+        value_name = "TRIPLE",
+        long = "target",
+        help = "Install the toolchain for the target triple (may be repeated or comma-separated)",
+        raw(required = "true", require_delimiter = "true")
+    )]
+    pub targets: Vec<String>,
+    #[structopt(
+        value_name = "CRATE",
+        long = "feature",
+        help = "Also install the support package for the given crate",
+        raw(number_of_values = "1")
+    )]
+    pub features: Vec<String>,
+    #[structopt(long = "all-features", help = "Also install all support packages for the target")]
+    pub all_features: bool,
+}
+
+#[derive(StructOpt)]
+pub struct RemoveOptions {
+    #[structopt(value_name = "TRIPLE", help = "Target triples to remove", raw(required = "true"))]
+    pub targets: Vec<String>,
+}
+
+#[derive(StructOpt)]
+pub struct ImportOptions {
+    #[structopt(
+        value_name = "ARCHIVE",
+        help = "Package archives (.tar.xz) as published on the mirror",
+        parse(from_os_str),
+        raw(required = "true")
+    )]
+    pub archives: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct ToolchainEntry {
+    target: String,
+    compiler: String,
+    version: String,
+    size: u64,
+    system: bool,
+    installed: bool,
+    features: Vec<FeatureEntry>,
+}
+
+#[derive(Serialize)]
+struct FeatureEntry {
+    crate_name: String,
+    crate_version_req: String,
+    libc: Option<String>,
+    size: u64,
+    installed: bool,
+}
+
+pub fn command(dirs: ProjectDirs, cmd: ToolchainCommand) -> Result<(), Error> {
+    match cmd {
+        ToolchainCommand::List(opts) => command_list(dirs, opts),
+        ToolchainCommand::Install(opts) => command_install(dirs, opts),
+        ToolchainCommand::Remove(opts) => command_remove(dirs, opts),
+        ToolchainCommand::Import(opts) => command_import(dirs, opts),
+    }
+}
+
+fn command_list(dirs: ProjectDirs, opts: ListOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, false)?;
+
+    let mut entries = vec![];
+    for target in manager.get_available_targets() {
+        let info = manager
+            .get_toolchain_info(&target)
+            .ok_or_else(|| format_err!("no toolchain available for target {}", target))?;
+        let features = manager
+            .get_toolchain_features(&target)
+            .into_iter()
+            .map(|feature| FeatureEntry {
+                crate_name: feature.crate_name,
+                crate_version_req: feature.crate_version_req,
+                libc: feature.libc,
+                size: feature.size,
+                installed: feature.installed,
+            })
+            .collect();
+        entries.push(ToolchainEntry {
+            installed: manager.is_toolchain_base_installed(&target),
+            target,
+            compiler: info.compiler,
+            version: info.version,
+            size: info.size,
+            system: info.system,
+            features,
+        });
+    }
+
+    if opts.format == "json" {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        eprintln!("No toolchains available for host {}.", manager.host());
+        return Ok(());
+    }
+
+    for entry in entries {
+        if entry.system {
+            println!(
+                "{} (system {} {})",
+                style(&entry.target).bold(),
+                entry.compiler,
+                entry.version
+            );
+        } else {
+            println!(
+                "{} ({} {}, {}){}",
+                style(&entry.target).bold(),
+                entry.compiler,
+                entry.version,
+                HumanBytes(entry.size),
+                installed_marker(entry.installed)
+            );
+        }
+        for feature in entry.features {
+            println!(
+                "    {} {} ({}{}){}",
+                feature.crate_name,
+                feature.crate_version_req,
+                feature.libc.map(|libc| format!("{}, ", libc)).unwrap_or_default(),
+                HumanBytes(feature.size),
+                installed_marker(feature.installed)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn command_install(dirs: ProjectDirs, opts: InstallOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, false)?;
+
+    // validate the whole selection before downloading anything
+    for target in opts.targets.iter() {
+        if !manager.is_toolchain_base_available(target) {
+            bail!("Cross compilation for target {} not supported.", target);
+        }
+        let features = manager.get_toolchain_features(target);
+        for crate_name in opts.features.iter() {
+            if !features.iter().any(|feature| &feature.crate_name == crate_name) {
+                bail!(
+                    "No support package for crate {} available for target {}.",
+                    crate_name,
+                    target
+                );
+            }
+        }
+    }
+
+    for target in opts.targets.iter() {
+        install_toolchain_base(&manager, target)?;
+
+        for feature in manager.get_toolchain_features(target) {
+            if !opts.all_features && !opts.features.contains(&feature.crate_name) {
+                continue;
+            }
+            eprintln!(
+                "{:>12} {} {}",
+                style("Support").magenta().bold(),
+                feature.crate_name,
+                feature.crate_version_req
+            );
+            if !feature.installed {
+                let install = manager.start_named_toolchain_feature_installation(
+                    target,
+                    &feature.crate_name,
+                    &feature.crate_version_req,
+                )?;
+                package_install_progress(install)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn command_remove(dirs: ProjectDirs, opts: RemoveOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, false)?;
+
+    for target in opts.targets.iter() {
+        if manager.remove_toolchain(target)? {
+            eprintln!("{:>12} {}", style("Removed").green().bold(), target);
+        } else {
+            eprintln!(
+                "{:>12} no toolchain installed for target {}",
+                style("Skipped").yellow().bold(),
+                target
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn command_import(dirs: ProjectDirs, opts: ImportOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, false)?;
+
+    for archive in opts.archives.iter() {
+        let (imported, install) = manager.start_package_import(archive)?;
+        match install {
+            Some(install) => {
+                eprintln!(
+                    "{:>12} {} {}",
+                    style("Import").magenta().bold(),
+                    imported.target,
+                    imported.description
+                );
+                package_install_progress(install)?;
+            }
+            None => {
+                eprintln!(
+                    "{:>12} {} {} is already installed",
+                    style("Skipped").yellow().bold(),
+                    imported.target,
+                    imported.description
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn installed_marker(installed: bool) -> String {
+    if installed {
+        format!(" {}", style("[installed]").green())
+    } else {
+        String::new()
+    }
+}
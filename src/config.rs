@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+
+use console::style;
+use directories::ProjectDirs;
+use failure::Error;
+use serde_json;
+use toml;
+
+use cargo::{self, ManifestOptions};
+
+/// User-level settings, read from `config.toml` in the configuration
+/// directory.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UserConfig {
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    /// Path or name of the clang used for clang toolchains.
+    pub clang: Option<String>,
+    #[serde(default)]
+    pub toolchain: BTreeMap<String, ToolchainConfig>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ToolchainConfig {
+    /// Prefix of a system-installed cross compiler for the target, used
+    /// instead of a toolchain from the registry.
+    pub prefix: Option<String>,
+}
+
+impl UserConfig {
+    pub fn load(dirs: &ProjectDirs) -> Result<UserConfig, Error> {
+        let path = dirs.config_dir().join("config.toml");
+        match fs::read_to_string(&path) {
+            Ok(source) => {
+                toml::from_str(&source).map_err(|err| format_err!("{}: {}", path.display(), err))
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(UserConfig::default()),
+            Err(err) => bail!("could not read {}: {}", path.display(), err),
+        }
+    }
+}
+
+/// Project-level settings, read from `Cross.toml` in the workspace root or
+/// from the `[package.metadata.cross]` table of the root package.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectConfig {
+    #[serde(default)]
+    pub targets: Vec<String>,
+    #[serde(default)]
+    pub target: BTreeMap<String, TargetConfig>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TargetConfig {
+    pub runner: Option<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub enable_features: Vec<String>,
+    #[serde(default)]
+    pub disable_features: Vec<String>,
+}
+
+impl ProjectConfig {
+    pub fn load(opts: &ManifestOptions) -> Result<ProjectConfig, Error> {
+        let workspace = cargo::workspace(opts)?;
+
+        let root_package = workspace
+            .packages
+            .iter()
+            .find(|package| package.manifest_path == workspace.workspace_root.join("Cargo.toml"));
+        let metadata = root_package
+            .and_then(|package| package.metadata.as_ref())
+            .and_then(|metadata| metadata.get("cross"));
+
+        let path = workspace.workspace_root.join("Cross.toml");
+        match fs::read_to_string(&path) {
+            Ok(source) => {
+                if metadata.is_some() {
+                    eprintln!(
+                        "{:>12} ignoring [package.metadata.cross] in favour of {}",
+                        style("Warning").yellow().bold(),
+                        path.display()
+                    );
+                }
+                debug!("using project config {}", path.display());
+                toml::from_str(&source).map_err(|err| format_err!("{}: {}", path.display(), err))
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => match metadata {
+                Some(metadata) => serde_json::from_value(metadata.clone())
+                    .map_err(|err| format_err!("invalid [package.metadata.cross]: {}", err)),
+                None => Ok(ProjectConfig::default()),
+            },
+            Err(err) => bail!("could not read {}: {}", path.display(), err),
+        }
+    }
+
+    /// Returns the targets selected on the command line, falling back to
+    /// the default targets of the project.
+    pub fn targets(&self, selected: &[String]) -> Result<Vec<String>, Error> {
+        if !selected.is_empty() {
+            return Ok(selected.to_vec());
+        }
+        if self.targets.is_empty() {
+            bail!("No target selected. Pass --target or set `targets` in Cross.toml.");
+        }
+        Ok(self.targets.clone())
+    }
+
+    pub fn runner(&self, target: &str) -> Option<&str> {
+        self.target
+            .get(target)
+            .and_then(|config| config.runner.as_deref())
+    }
+
+    pub fn env(&self, target: &str) -> Vec<(String, OsString)> {
+        self.target
+            .get(target)
+            .map(|config| {
+                config
+                    .env
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.into()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn is_feature_disabled(&self, target: &str, crate_name: &str) -> bool {
+        self.target
+            .get(target)
+            .map(|config| config.disable_features.iter().any(|name| name == crate_name))
+            .unwrap_or(false)
+    }
+
+    pub fn enabled_features(&self, target: &str) -> &[String] {
+        self.target
+            .get(target)
+            .map(|config| &config.enable_features[..])
+            .unwrap_or(&[])
+    }
+}
@@ -19,26 +19,38 @@ extern crate sha1;
 extern crate structopt;
 extern crate tar;
 extern crate tempfile;
+extern crate toml;
 extern crate xz2;
 
+use std::ffi::OsString;
 use std::process;
 
 use console::style;
 use directories::ProjectDirs;
 use failure::Error;
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
 mod cargo;
+mod commands;
+mod config;
 mod package;
+mod registry;
+mod system;
 mod toolchains;
+mod triple;
 mod utils;
 
-use self::cargo::CargoOptions;
-use self::package::PackageInstall;
+use self::cargo::{CargoOptions, RunOptions, TestOptions};
+use self::commands::cache::CacheCommand;
+use self::commands::doctor::DoctorOptions;
+use self::commands::env::EnvOptions;
+use self::commands::exec::{ExecOptions, ShellOptions};
+use self::commands::prepare_toolchain;
+use self::commands::support::SupportOptions;
+use self::commands::toolchain::ToolchainCommand;
+use self::config::ProjectConfig;
 use self::toolchains::ToolchainManager;
-use self::utils::progress::ProgressObserver;
 
 #[derive(StructOpt)]
 #[structopt(
@@ -63,6 +75,66 @@ enum Command {
         version = ""
     )]
     Build(CargoOptions),
+    #[structopt(
+        name = "check",
+        about = "Check a local package and all of its dependencies for errors",
+        author = "",
+        version = ""
+    )]
+    Check(CargoOptions),
+    #[structopt(
+        name = "test",
+        about = "Execute all unit and integration tests of a local package",
+        author = "",
+        version = ""
+    )]
+    Test(TestOptions),
+    #[structopt(
+        name = "run",
+        about = "Run the main binary of the local package (src/main.rs)",
+        author = "",
+        version = ""
+    )]
+    Run(RunOptions),
+    #[structopt(name = "toolchain", about = "Manage cross toolchains", author = "", version = "")]
+    Toolchain(ToolchainCommand),
+    #[structopt(name = "cache", about = "Manage the toolchain cache", author = "", version = "")]
+    Cache(CacheCommand),
+    #[structopt(
+        name = "env",
+        about = "Print the cross environment for use in shells and other tools",
+        author = "",
+        version = ""
+    )]
+    Env(EnvOptions),
+    #[structopt(
+        name = "exec",
+        about = "Run a command in the cross environment",
+        author = "",
+        version = ""
+    )]
+    Exec(ExecOptions),
+    #[structopt(
+        name = "shell",
+        about = "Open an interactive shell in the cross environment",
+        author = "",
+        version = ""
+    )]
+    Shell(ShellOptions),
+    #[structopt(
+        name = "doctor",
+        about = "Diagnose problems with the setup for a target",
+        author = "",
+        version = ""
+    )]
+    Doctor(DoctorOptions),
+    #[structopt(
+        name = "support",
+        about = "Report native dependencies and their support packages",
+        author = "",
+        version = ""
+    )]
+    Support(SupportOptions),
 }
 
 fn main() {
@@ -82,88 +154,122 @@ fn main() {
 fn command(dirs: ProjectDirs, cmd: Command) -> Result<(), Error> {
     match cmd {
         Command::Build(opts) => command_build(dirs, opts),
+        Command::Check(opts) => command_check(dirs, opts),
+        Command::Test(opts) => command_test(dirs, opts),
+        Command::Run(opts) => command_run(dirs, opts),
+        Command::Toolchain(cmd) => commands::toolchain::command(dirs, cmd),
+        Command::Cache(cmd) => commands::cache::command(dirs, cmd),
+        Command::Env(opts) => commands::env::command(dirs, opts),
+        Command::Exec(opts) => commands::exec::command_exec(dirs, opts),
+        Command::Shell(opts) => commands::exec::command_shell(dirs, opts),
+        Command::Doctor(opts) => commands::doctor::command(dirs, opts),
+        Command::Support(opts) => commands::support::command(dirs, opts),
     }
 }
 
 fn command_build(dirs: ProjectDirs, opts: CargoOptions) -> Result<(), Error> {
-    let manager = ToolchainManager::new(&dirs);
-
-    let info = manager.get_toolchain_info(&opts.target).ok_or_else(|| {
-        format_err!(
-            "Cross compilation for target {} not supported.",
-            opts.target
-        )
-    })?;
-
-    if !manager.is_toolchain_base_available(&opts.target) {
-        bail!(
-            "Could not find suitable toolchain for selected target ({}) and host ({}) system.",
-            opts.target,
-            manager.host(),
-        );
-    }
+    let manager = ToolchainManager::new(&dirs, opts.manifest.is_offline())?;
+    let config = ProjectConfig::load(&opts.manifest)?;
 
-    eprintln!(
-        "{:>12} {} (gcc {})",
-        style("Toolchain").magenta().bold(),
-        opts.target,
-        info.gcc_version
-    );
+    for_each_target(&manager, &config, &opts, |target, env| {
+        cargo::build(&opts, target, env)
+    })
+}
 
-    if !manager.is_toolchain_base_installed(&opts.target) {
-        let install = manager.start_toolchain_base_installation(&opts.target)?;
-        package_install_progress(install)?;
-    }
+fn command_check(dirs: ProjectDirs, opts: CargoOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, opts.manifest.is_offline())?;
+    let config = ProjectConfig::load(&opts.manifest)?;
 
-    let metadata = cargo::metadata(&opts)?;
-
-    for package in metadata.packages.iter() {
-        if manager.is_toolchain_feature_available(&opts.target, &package) {
-            eprintln!(
-                "{:>12} {} v{}",
-                style("Support").magenta().bold(),
-                package.name,
-                package.version
-            );
-            if !manager.is_toolchain_feature_installed(&opts.target, &package) {
-                let install = manager.start_toolchain_feature_installation(&opts.target, &package)?;
-                package_install_progress(install)?;
-            }
-        }
-    }
+    for_each_target(&manager, &config, &opts, |target, env| {
+        cargo::check(&opts, target, env)
+    })
+}
+
+fn command_test(dirs: ProjectDirs, opts: TestOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, opts.cargo.manifest.is_offline())?;
+    let config = ProjectConfig::load(&opts.cargo.manifest)?;
+
+    for_each_target(&manager, &config, &opts.cargo, |target, mut env| {
+        let runner = opts.runner.as_deref().or_else(|| config.runner(target));
+        env.extend(manager.get_toolchain_runner_environment(target, runner)?);
+        cargo::test(&opts, target, env)
+    })
+}
 
-    let env = manager.get_toolchain_environment(&opts.target, &metadata)?;
-    let status = cargo::build(&opts, env)?;
+fn command_run(dirs: ProjectDirs, opts: RunOptions) -> Result<(), Error> {
+    let manager = ToolchainManager::new(&dirs, opts.cargo.manifest.is_offline())?;
+    let config = ProjectConfig::load(&opts.cargo.manifest)?;
+
+    let targets = config.targets(&opts.cargo.targets)?;
+    let target = match targets[..] {
+        [ref target] => target,
+        _ => bail!("Running a binary requires exactly one target."),
+    };
+
+    let mut env = prepare_toolchain(&manager, &opts.cargo.manifest, &config, target, false)?;
+
+    let runner = opts.runner.as_deref().or_else(|| config.runner(target));
+    env.extend(manager.get_toolchain_runner_environment(target, runner)?);
+
+    let status = cargo::run(&opts, target, env)?;
     if !status.success() {
-        process::exit(1);
+        process::exit(status.code().unwrap_or(1));
     }
 
     Ok(())
 }
 
-fn package_install_progress(install: PackageInstall) -> Result<(), Error> {
-    let progress_bar = ProgressBar::new(install.total());
-    progress_bar.set_draw_target(ProgressDrawTarget::stderr());
-    progress_bar.set_style(
-        ProgressStyle::default_bar()
-            .template("{msg:>12.cyan.bold} {bytes} / {total_bytes} [{wide_bar}] {percent}%  ")
-            .progress_chars("=>-"),
-    );
-    progress_bar.set_message("Fetch");
-
-    struct ProgressBarObserver(ProgressBar);
-
-    impl ProgressObserver for ProgressBarObserver {
-        fn progress(&mut self, delta: u64) {
-            self.0.inc(delta);
+/// Prepares the toolchain for each selected target (or the project's
+/// default targets) in turn and runs `f` with the resulting environment.
+/// When more than one target is selected, a failing target does not stop
+/// the remaining ones, and a per-target summary is printed at the end.
+fn for_each_target<F>(
+    manager: &ToolchainManager,
+    config: &ProjectConfig,
+    opts: &CargoOptions,
+    mut f: F,
+) -> Result<(), Error>
+where
+    F: FnMut(&str, Vec<(String, OsString)>) -> Result<process::ExitStatus, Error>,
+{
+    let targets = config.targets(&opts.targets)?;
+
+    if let [ref target] = targets[..] {
+        let env = prepare_toolchain(manager, &opts.manifest, config, target, false)?;
+        let status = f(target, env)?;
+        if !status.success() {
+            process::exit(1);
         }
+        return Ok(());
+    }
 
-        fn complete(&mut self) {
-            self.0.finish_and_clear();
+    let mut results = vec![];
+    for target in targets.iter() {
+        let result = prepare_toolchain(manager, &opts.manifest, config, target, false)
+            .and_then(|env| f(target, env));
+        results.push((target, result));
+    }
+
+    let mut failed = false;
+    for (target, result) in results {
+        match result {
+            Ok(ref status) if status.success() => {
+                eprintln!("{:>12} {}", style("Finished").green().bold(), target);
+            }
+            Ok(_) => {
+                failed = true;
+                eprintln!("{:>12} {}", style("Failed").red().bold(), target);
+            }
+            Err(err) => {
+                failed = true;
+                eprintln!("{:>12} {} ({})", style("Failed").red().bold(), target, err);
+            }
         }
     }
 
-    install.perform(ProgressBarObserver(progress_bar))?;
+    if failed {
+        process::exit(1);
+    }
 
     Ok(())
 }
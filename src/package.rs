@@ -1,27 +1,44 @@
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::Duration;
 
-use chttp::Client;
+use chttp::{Client, Options};
 use failure::Error;
 use tar::Archive;
-use tempfile;
+use tempfile::{self, TempDir};
 use xz2::read::XzDecoder;
 
 use utils::hasher;
 use utils::progress;
 
+/// Timeouts for fetching metadata, which happens on every run and should not
+/// hold up commands when the mirror is unreachable.
+const FETCH_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
 pub struct PackageManager {
-    base_url: String,
+    mirrors: Vec<String>,
     client: Rc<Client>,
+    fetch_client: Client,
 }
 
 impl PackageManager {
-    pub fn new(base_url: &str) -> Result<Self, Error> {
+    /// Creates a package manager that tries the given mirrors in order.
+    pub fn new(mirrors: Vec<String>) -> Result<Self, Error> {
         let client = Client::new();
+        let fetch_client = Client::builder()
+            .options(Options {
+                connect_timeout: FETCH_CONNECT_TIMEOUT,
+                timeout: Some(FETCH_TIMEOUT),
+                ..Options::default()
+            })
+            .build();
         Ok(PackageManager {
-            base_url: base_url.to_owned(),
+            mirrors,
             client: Rc::new(client),
+            fetch_client,
         })
     }
 
@@ -29,27 +46,110 @@ impl PackageManager {
         &self,
         remote_path: &str,
         total_size: u64,
-        checksum: &'static str,
+        checksum: &str,
         local_path: PathBuf,
     ) -> Result<PackageInstall, Error> {
         debug!("install {}", remote_path);
-        let url = format!("{}/{}", self.base_url, remote_path);
+        let urls = self.urls(remote_path);
         let client = self.client.clone();
         Ok(PackageInstall {
             total_size,
-            checksum,
+            checksum: checksum.to_owned(),
             client,
-            url,
+            urls,
             local_path,
         })
     }
+
+    /// Prepares the installation of a package from a local archive, which
+    /// is verified against the checksum just like a downloaded one.
+    pub fn import(
+        &self,
+        archive: &Path,
+        checksum: &str,
+        local_path: PathBuf,
+    ) -> Result<PackageInstall, Error> {
+        debug!("import {}", archive.display());
+        let total_size = fs::metadata(archive)?.len();
+        let client = self.client.clone();
+        Ok(PackageInstall {
+            total_size,
+            checksum: checksum.to_owned(),
+            client,
+            urls: vec![format!("file://{}", archive.display())],
+            local_path,
+        })
+    }
+
+    pub fn fetch(&self, remote_path: &str) -> Result<Vec<u8>, Error> {
+        debug!("fetch {}", remote_path);
+        try_urls(&self.urls(remote_path), |url| {
+            let mut data = vec![];
+            open(&self.fetch_client, url)?.read_to_end(&mut data)?;
+            Ok(data)
+        })
+    }
+
+    fn urls(&self, remote_path: &str) -> Vec<String> {
+        // user registry entries may point to packages hosted elsewhere
+        if remote_path.contains("://") {
+            vec![remote_path.to_owned()]
+        } else {
+            self.mirrors
+                .iter()
+                .map(|mirror| format!("{}/{}", mirror.trim_end_matches('/'), remote_path))
+                .collect()
+        }
+    }
+}
+
+/// Computes the checksum of a local archive, as used by registry entries.
+pub fn checksum(archive: &Path) -> Result<String, Error> {
+    let file = fs::File::open(archive)
+        .map_err(|err| format_err!("could not read {}: {}", archive.display(), err))?;
+    let mut hash = hasher::ReadHasher::new(file);
+    io::copy(&mut hash, &mut io::sink())?;
+    Ok(hash.digest_hex())
+}
+
+/// Calls `f` with each of the urls in turn until it succeeds, so that a
+/// failure on one mirror falls back to the next.
+fn try_urls<T, F>(urls: &[String], mut f: F) -> Result<T, Error>
+where
+    F: FnMut(&str) -> Result<T, Error>,
+{
+    let mut last_err = format_err!("no mirror configured");
+    for url in urls {
+        match f(url) {
+            Ok(value) => return Ok(value),
+            Err(err) => {
+                warn!("could not retrieve {}: {}", url, err);
+                last_err = err;
+            }
+        }
+    }
+    Err(last_err)
+}
+
+/// Opens the url for reading. `file://` urls are read from the local file
+/// system.
+fn open(client: &Client, url: &str) -> Result<Box<dyn Read>, Error> {
+    debug!("open {}", url);
+    if let Some(path) = url.strip_prefix("file://") {
+        return Ok(Box::new(fs::File::open(path)?));
+    }
+    let response = client.get(url)?;
+    if !response.status().is_success() {
+        bail!("unexpected status code {}", response.status());
+    }
+    Ok(Box::new(response.into_body()))
 }
 
 pub struct PackageInstall {
     total_size: u64,
-    checksum: &'static str,
+    checksum: String,
     client: Rc<Client>,
-    url: String,
+    urls: Vec<String>,
     local_path: PathBuf,
 }
 
@@ -58,19 +158,37 @@ impl PackageInstall {
         self.total_size
     }
 
-    pub fn perform<P>(self, observer: P) -> Result<(), Error>
+    pub fn perform<P>(self, mut observer: P) -> Result<(), Error>
     where
         P: progress::ProgressObserver + Send + 'static,
     {
-        let temp_dir = tempfile::tempdir()?;
-        debug!("temp dir {:?}", temp_dir);
+        // a broken transfer or a stale mirror is retried with the next one
+        let temp_dir = try_urls(&self.urls, |url| {
+            self.unpack(url, &mut observer)
+                .inspect_err(|_| observer.restart())
+        })?;
+
+        fs::create_dir_all(self.local_path.parent().unwrap())?;
 
-        let response = self.client.get(&self.url)?;
-        if !response.status().is_success() {
-            bail!("unexpected status code {}", response.status());
+        if let Err(err) = fs::rename(temp_dir, &self.local_path) {
+            // a concurrent process might have installed the package,
+            // so we check if the path exists
+            if !self.local_path.exists() {
+                return Err(err.into());
+            }
         }
 
-        let read = observer.observe_read(response.into_body());
+        Ok(())
+    }
+
+    fn unpack<P>(&self, url: &str, observer: P) -> Result<TempDir, Error>
+    where
+        P: progress::ProgressObserver,
+    {
+        let temp_dir = tempfile::tempdir()?;
+        debug!("temp dir {:?}", temp_dir);
+
+        let read = observer.observe_read(open(&self.client, url)?);
         let hash = hasher::ReadHasher::new(read);
         let bunzip = XzDecoder::new(hash);
         let mut archive = Archive::new(bunzip);
@@ -85,16 +203,6 @@ impl PackageInstall {
             );
         }
 
-        fs::create_dir_all(self.local_path.parent().unwrap())?;
-
-        if let Err(err) = fs::rename(temp_dir, &self.local_path) {
-            // a concurrent process might have installed the package,
-            // so we check if the path exists
-            if !self.local_path.exists() {
-                return Err(err.into());
-            }
-        }
-
-        Ok(())
+        Ok(temp_dir)
     }
 }
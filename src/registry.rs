@@ -0,0 +1,618 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use failure::Error;
+use semver::VersionReq;
+use serde::de::DeserializeOwned;
+use serde_json;
+use tempfile::NamedTempFile;
+use toml;
+
+use package::PackageManager;
+
+/// Version of the registry manifest format understood by this release.
+/// The manifest is fetched from a versioned path on the mirror, so that
+/// older releases keep reading a format they understand.
+const REGISTRY_VERSION: u32 = 1;
+
+const REGISTRY_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Registry entries compiled into the binary, used when no manifest could
+/// be fetched from the mirror and none is cached.
+static BUILTIN_REGISTRY: &str = include_str!("registry.json");
+
+#[derive(Deserialize, Serialize)]
+pub struct Registry {
+    pub version: u32,
+    pub toolchains: Vec<ToolchainBase>,
+    pub features: Vec<ToolchainFeature>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ToolchainBase {
+    pub target_platform_triple: String,
+    /// Host the toolchain runs on. Not used for clang toolchains, whose
+    /// package only contains the target sysroot.
+    #[serde(default)]
+    pub host_platform_triple: String,
+    #[serde(default)]
+    pub kind: ToolchainKind,
+    #[serde(default)]
+    pub gcc_version: String,
+    /// GNU triple the toolchain was built for, if it differs from the Rust
+    /// triple (e.g. `arm-linux-gnueabihf`).
+    #[serde(default)]
+    pub gnu_triple: Option<String>,
+    /// Prefix of the compiler and binutils within the package, if it
+    /// differs from `bin/<gnu triple>-`.
+    #[serde(default)]
+    pub tool_prefix: Option<String>,
+    pub path: String,
+    pub checksum: String,
+    pub size: u64,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolchainKind {
+    /// A gcc cross compiler together with its sysroot.
+    #[default]
+    Gcc,
+    /// A sysroot for use with the clang and lld installed on the host.
+    Clang,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ToolchainFeature {
+    pub target_platform_triple: String,
+    pub crate_name: String,
+    pub crate_version_req: String,
+    /// The libc the package was built against (`gnu` or `musl`), which has
+    /// to match the target.
+    #[serde(default)]
+    pub libc: Option<String>,
+    pub path: String,
+    pub size: u64,
+    pub checksum: String,
+    #[serde(default)]
+    pub env_vars: BTreeMap<String, String>,
+}
+
+impl Registry {
+    pub fn builtin() -> Registry {
+        Registry::parse(BUILTIN_REGISTRY.as_bytes()).expect("failed to parse built-in registry")
+    }
+
+    /// Loads the registry manifest, refreshing the cached copy from the
+    /// mirror once the last attempt is older than the refresh interval,
+    /// unless `offline` is set. Falls back to a stale cached copy, and then
+    /// to the built-in entries, when the mirror cannot be reached.
+    pub fn load(cache_dir: &Path, package_manager: &PackageManager, offline: bool) -> Registry {
+        let cache_path = cache_dir.join(format!("registry-v{}.json", REGISTRY_VERSION));
+        let checked_path = cache_dir.join(format!("registry-v{}.checked", REGISTRY_VERSION));
+
+        if !offline && !is_fresh(&checked_path) {
+            // record the attempt up front, so that an unreachable mirror is
+            // only tried again after a full interval
+            if let Err(err) = touch(&checked_path) {
+                debug!("could not record registry refresh: {}", err);
+            }
+            if let Err(err) = refresh(&cache_path, package_manager) {
+                warn!("could not refresh toolchain registry: {}", err);
+            }
+        }
+
+        match fs::read(&cache_path)
+            .map_err(Error::from)
+            .and_then(|data| Registry::parse(&data))
+        {
+            Ok(registry) => registry,
+            Err(err) => {
+                debug!("using built-in toolchain registry: {}", err);
+                Registry::builtin()
+            }
+        }
+    }
+
+    /// Merges the entries declared in the user registry file at `path`, if
+    /// it exists.
+    ///
+    /// A user toolchain replaces the entry for the same target and host, and
+    /// a user feature replaces the entry for the same target, crate and
+    /// version requirement. User features are also consulted before all
+    /// other features, so that a narrower version requirement takes
+    /// precedence over a broader one from the mirror.
+    pub fn merge_user_file(&mut self, path: &Path) -> Result<(), Error> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => bail!("could not read {}: {}", path.display(), err),
+        };
+
+        let (toolchains, features) = parse_user(&source).map_err(|err| match err.line {
+            Some(line) => format_err!("{}:{}: {}", path.display(), line, err.message),
+            None => format_err!("{}: {}", path.display(), err.message),
+        })?;
+
+        debug!(
+            "merging {} toolchains and {} features from {}",
+            toolchains.len(),
+            features.len(),
+            path.display()
+        );
+
+        self.toolchains.retain(|t| {
+            !toolchains.iter().any(|u| {
+                u.target_platform_triple == t.target_platform_triple
+                    && u.host_platform_triple == t.host_platform_triple
+            })
+        });
+        self.features.retain(|t| {
+            !features.iter().any(|u| {
+                u.target_platform_triple == t.target_platform_triple
+                    && u.crate_name == t.crate_name
+                    && u.crate_version_req == t.crate_version_req
+            })
+        });
+
+        let mut merged_toolchains = toolchains;
+        merged_toolchains.append(&mut self.toolchains);
+        self.toolchains = merged_toolchains;
+
+        let mut merged_features = features;
+        merged_features.append(&mut self.features);
+        self.features = merged_features;
+
+        Ok(())
+    }
+
+    fn parse(data: &[u8]) -> Result<Registry, Error> {
+        let mut registry: Registry = serde_json::from_slice(data)?;
+        if registry.version != REGISTRY_VERSION {
+            bail!(
+                "unsupported registry version {} (expected {})",
+                registry.version,
+                REGISTRY_VERSION
+            );
+        }
+        // the manifest comes from the mirror, so drop broken entries instead
+        // of failing on them later
+        registry.toolchains.retain(|base| {
+            if !is_valid_checksum(&base.checksum) {
+                warn!(
+                    "ignoring toolchain for target {} with invalid checksum `{}`",
+                    base.target_platform_triple, base.checksum
+                );
+                return false;
+            }
+            true
+        });
+        registry.features.retain(|feature| {
+            if !is_valid_checksum(&feature.checksum) {
+                warn!(
+                    "ignoring {} {} for target {} with invalid checksum `{}`",
+                    feature.crate_name,
+                    feature.crate_version_req,
+                    feature.target_platform_triple,
+                    feature.checksum
+                );
+                return false;
+            }
+            if VersionReq::parse(&feature.crate_version_req).is_err() {
+                warn!(
+                    "ignoring {} for target {} with invalid crate_version_req `{}`",
+                    feature.crate_name, feature.target_platform_triple, feature.crate_version_req
+                );
+                return false;
+            }
+            if !feature.matches_target_libc() {
+                warn!(
+                    "ignoring {} {} for target {} built against {:?}",
+                    feature.crate_name,
+                    feature.crate_version_req,
+                    feature.target_platform_triple,
+                    feature.libc
+                );
+                return false;
+            }
+            true
+        });
+        Ok(registry)
+    }
+}
+
+impl ToolchainBase {
+    pub fn gnu_triple(&self) -> &str {
+        self.gnu_triple
+            .as_ref()
+            .unwrap_or(&self.target_platform_triple)
+    }
+
+    pub fn tool_prefix(&self) -> String {
+        self.tool_prefix
+            .clone()
+            .unwrap_or_else(|| format!("bin/{}-", self.gnu_triple()))
+    }
+}
+
+impl ToolchainFeature {
+    fn matches_target_libc(&self) -> bool {
+        match self.libc {
+            Some(ref libc) => Some(libc.as_str()) == target_libc(&self.target_platform_triple),
+            None => true,
+        }
+    }
+}
+
+/// Returns the libc of the target, as named by the `libc` of features.
+pub fn target_libc(target: &str) -> Option<&'static str> {
+    match target.rsplit('-').next() {
+        Some(env) if env.starts_with("musl") => Some("musl"),
+        Some(env) if env.starts_with("gnu") => Some("gnu"),
+        _ => None,
+    }
+}
+
+fn is_fresh(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .map(|age| age < REGISTRY_REFRESH_INTERVAL)
+        .unwrap_or(false)
+}
+
+fn touch(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, b"")
+}
+
+fn refresh(cache_path: &Path, package_manager: &PackageManager) -> Result<(), Error> {
+    let data = package_manager.fetch(&format!("registry/v{}.json", REGISTRY_VERSION))?;
+
+    // make sure we never replace a usable cache with a broken manifest
+    Registry::parse(&data)?;
+
+    let cache_dir = cache_path.parent().unwrap();
+    fs::create_dir_all(cache_dir)?;
+    let mut file = NamedTempFile::new_in(cache_dir)?;
+    file.write_all(&data)?;
+    file.persist(cache_path)?;
+
+    Ok(())
+}
+
+struct UserRegistryError {
+    line: Option<usize>,
+    message: String,
+}
+
+impl UserRegistryError {
+    fn new(line: Option<usize>, message: String) -> UserRegistryError {
+        UserRegistryError { line, message }
+    }
+}
+
+fn parse_user(
+    source: &str,
+) -> Result<(Vec<ToolchainBase>, Vec<ToolchainFeature>), UserRegistryError> {
+    // syntax errors already mention the line they occur on
+    let mut table: toml::value::Table =
+        toml::from_str(source).map_err(|err| UserRegistryError::new(None, err.to_string()))?;
+
+    let toolchains: Vec<(Option<usize>, ToolchainBase)> =
+        parse_user_entries(source, &mut table, "toolchains")?;
+    let features: Vec<(Option<usize>, ToolchainFeature)> =
+        parse_user_entries(source, &mut table, "features")?;
+
+    if let Some(key) = table.keys().next() {
+        return Err(UserRegistryError::new(
+            find_key_line(source, key),
+            format!("unknown key `{}` (expected `toolchains` or `features`)", key),
+        ));
+    }
+
+    for (index, &(line, ref base)) in toolchains.iter().enumerate() {
+        validate_checksum(line, &base.checksum)?;
+        if base.kind == ToolchainKind::Gcc {
+            if base.host_platform_triple.is_empty() {
+                return Err(UserRegistryError::new(
+                    line,
+                    "missing field `host_platform_triple`".to_owned(),
+                ));
+            }
+            if base.gcc_version.is_empty() {
+                return Err(UserRegistryError::new(
+                    line,
+                    "missing field `gcc_version`".to_owned(),
+                ));
+            }
+        }
+        if toolchains[..index].iter().any(|(_, other)| {
+            other.target_platform_triple == base.target_platform_triple
+                && other.host_platform_triple == base.host_platform_triple
+        }) {
+            return Err(UserRegistryError::new(
+                line,
+                format!(
+                    "duplicate toolchain for target {} and host {}",
+                    base.target_platform_triple, base.host_platform_triple
+                ),
+            ));
+        }
+    }
+
+    for (index, &(line, ref feature)) in features.iter().enumerate() {
+        validate_checksum(line, &feature.checksum)?;
+        if !feature.matches_target_libc() {
+            return Err(UserRegistryError::new(
+                line,
+                format!(
+                    "libc `{}` does not match target {}",
+                    feature.libc.as_ref().unwrap(),
+                    feature.target_platform_triple
+                ),
+            ));
+        }
+        if let Err(err) = VersionReq::parse(&feature.crate_version_req) {
+            return Err(UserRegistryError::new(
+                line,
+                format!(
+                    "invalid crate_version_req `{}`: {}",
+                    feature.crate_version_req, err
+                ),
+            ));
+        }
+        if features[..index].iter().any(|(_, other)| {
+            other.target_platform_triple == feature.target_platform_triple
+                && other.crate_name == feature.crate_name
+                && other.crate_version_req == feature.crate_version_req
+        }) {
+            return Err(UserRegistryError::new(
+                line,
+                format!(
+                    "duplicate feature {} {} for target {}",
+                    feature.crate_name, feature.crate_version_req, feature.target_platform_triple
+                ),
+            ));
+        }
+    }
+
+    Ok((
+        toolchains.into_iter().map(|(_, base)| base).collect(),
+        features.into_iter().map(|(_, feature)| feature).collect(),
+    ))
+}
+
+fn parse_user_entries<T: DeserializeOwned>(
+    source: &str,
+    table: &mut toml::value::Table,
+    key: &str,
+) -> Result<Vec<(Option<usize>, T)>, UserRegistryError> {
+    let entries = match table.remove(key) {
+        Some(toml::Value::Array(entries)) => entries,
+        Some(_) => {
+            return Err(UserRegistryError::new(
+                find_key_line(source, key),
+                format!("`{}` must be an array of tables", key),
+            ))
+        }
+        None => return Ok(vec![]),
+    };
+
+    let header = format!("[[{}]]", key);
+    let mut headers = source
+        .lines()
+        .enumerate()
+        .filter(|&(_, line)| line.trim().starts_with(&header))
+        .map(|(index, _)| index + 1);
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            let line = headers.next();
+            entry
+                .try_into()
+                .map(|entry| (line, entry))
+                .map_err(|err| {
+                    UserRegistryError::new(line, format!("invalid {} entry: {}", key, err))
+                })
+        })
+        .collect()
+}
+
+fn validate_checksum(line: Option<usize>, checksum: &str) -> Result<(), UserRegistryError> {
+    if !is_valid_checksum(checksum) {
+        return Err(UserRegistryError::new(
+            line,
+            format!("invalid checksum `{}` (expected a sha1 hex digest)", checksum),
+        ));
+    }
+    Ok(())
+}
+
+fn is_valid_checksum(checksum: &str) -> bool {
+    checksum.len() == 40 && checksum.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Finds the line defining the top-level key, either as `key = ...` or as
+/// a `[key]` or `[[key]]` header.
+fn find_key_line(source: &str, key: &str) -> Option<usize> {
+    find_line(source, |line| {
+        let line = line.trim_start_matches('[');
+        line.starts_with(key)
+            && line[key.len()..]
+                .trim_start()
+                .starts_with(&[']', '=', '.'][..])
+    })
+}
+
+fn find_line<F: Fn(&str) -> bool>(source: &str, predicate: F) -> Option<usize> {
+    source
+        .lines()
+        .position(|line| predicate(line.trim()))
+        .map(|index| index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toolchain(target: &str, checksum: &str) -> String {
+        format!(
+            r#"
+[[toolchains]]
+target_platform_triple = "{}"
+host_platform_triple = "x86_64-apple-darwin"
+gcc_version = "4.8.5"
+path = "toolchain.tar.xz"
+size = 1
+checksum = "{}"
+"#,
+            target, checksum
+        )
+    }
+
+    fn feature(target: &str, version_req: &str, libc: &str) -> String {
+        format!(
+            r#"
+[[features]]
+target_platform_triple = "{}"
+crate_name = "libfoo-sys"
+crate_version_req = "{}"
+libc = "{}"
+path = "feature.tar.xz"
+size = 1
+checksum = "3c3476b32a284fb5f6096e4ad025d4786508e118"
+"#,
+            target, version_req, libc
+        )
+    }
+
+    fn parse_error(source: &str) -> (Option<usize>, String) {
+        match parse_user(source) {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => (err.line, err.message),
+        }
+    }
+
+    const CHECKSUM: &str = "4de476857501db4748be7e3df4b42c52a3772ad6";
+
+    #[test]
+    fn parse_user_entries_in_order() {
+        let source = toolchain("x86_64-unknown-linux-gnu", CHECKSUM)
+            + &feature("x86_64-unknown-linux-gnu", "^1.2", "gnu")
+            + &toolchain("aarch64-unknown-linux-gnu", CHECKSUM);
+        let (toolchains, features) = match parse_user(&source) {
+            Ok(entries) => entries,
+            Err(err) => panic!("{}", err.message),
+        };
+
+        let targets: Vec<&str> = toolchains
+            .iter()
+            .map(|base| base.target_platform_triple.as_str())
+            .collect();
+        assert_eq!(
+            targets,
+            ["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu"]
+        );
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0].crate_version_req, "^1.2");
+    }
+
+    #[test]
+    fn reports_line_of_invalid_entry() {
+        let source = toolchain("x86_64-unknown-linux-gnu", CHECKSUM)
+            + "\n[[toolchains]]\ntarget_platform_triple = \"aarch64-unknown-linux-gnu\"\n";
+        let (line, message) = parse_error(&source);
+        assert_eq!(line, Some(10));
+        assert!(message.starts_with("invalid toolchains entry: "), "{}", message);
+    }
+
+    #[test]
+    fn reports_line_of_duplicate_toolchain() {
+        let source = toolchain("x86_64-unknown-linux-gnu", CHECKSUM)
+            + &toolchain("x86_64-unknown-linux-gnu", CHECKSUM);
+        let (line, message) = parse_error(&source);
+        assert_eq!(line, Some(10));
+        assert_eq!(
+            message,
+            "duplicate toolchain for target x86_64-unknown-linux-gnu and host x86_64-apple-darwin"
+        );
+    }
+
+    #[test]
+    fn reports_line_of_duplicate_feature() {
+        let source = feature("x86_64-unknown-linux-gnu", "^1.2", "gnu")
+            + &feature("x86_64-unknown-linux-gnu", "^1.2", "gnu");
+        assert_eq!(
+            parse_error(&source),
+            (
+                Some(11),
+                "duplicate feature libfoo-sys ^1.2 for target x86_64-unknown-linux-gnu".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn reports_libc_mismatch() {
+        let source = toolchain("x86_64-unknown-linux-gnu", CHECKSUM)
+            + &feature("x86_64-unknown-linux-gnu", "^1.2", "musl");
+        assert_eq!(
+            parse_error(&source),
+            (
+                Some(10),
+                "libc `musl` does not match target x86_64-unknown-linux-gnu".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn reports_invalid_checksum() {
+        let source = toolchain("x86_64-unknown-linux-gnu", "4de47685");
+        assert_eq!(
+            parse_error(&source),
+            (
+                Some(2),
+                "invalid checksum `4de47685` (expected a sha1 hex digest)".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn reports_invalid_version_req() {
+        let (line, message) = parse_error(&feature("x86_64-unknown-linux-gnu", "one", "gnu"));
+        assert_eq!(line, Some(2));
+        assert!(
+            message.starts_with("invalid crate_version_req `one`: "),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn reports_line_of_unknown_key() {
+        let source =
+            toolchain("x86_64-unknown-linux-gnu", CHECKSUM) + "\n[[toolchain]]\npath = \"x\"\n";
+        assert_eq!(
+            parse_error(&source),
+            (
+                Some(10),
+                "unknown key `toolchain` (expected `toolchains` or `features`)".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn reports_line_of_non_array_key() {
+        let source =
+            "features = \"none\"\n".to_owned() + &toolchain("x86_64-unknown-linux-gnu", CHECKSUM);
+        assert_eq!(
+            parse_error(&source),
+            (Some(1), "`features` must be an array of tables".to_owned())
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use triple;
+
+/// A cross compiler installed on the system, e.g. from a distribution
+/// package, which is used as a toolchain base without downloading anything.
+#[derive(Clone)]
+pub struct SystemToolchain {
+    pub gcc_path: PathBuf,
+    pub gcc_version: String,
+    pub sysroot: Option<PathBuf>,
+}
+
+/// Looks for a cross compiler for the target, either using the configured
+/// prefix (e.g. `/opt/x-tools/bin/aarch64-linux-gnu-`) or on the `PATH`
+/// under one of the prefixes distributions commonly use.
+pub fn detect(target: &str, prefix: Option<&str>) -> Option<SystemToolchain> {
+    let gcc_path = match prefix {
+        Some(prefix) => find_program(&format!("{}gcc", prefix)),
+        None => find_compiler(target),
+    }?;
+
+    let gcc_version = match query(&gcc_path, "-dumpversion") {
+        Some(version) => version,
+        None => {
+            warn!("could not query version of {}", gcc_path.display());
+            return None;
+        }
+    };
+
+    // distribution packages are often configured without a sysroot, and
+    // install the target libraries next to the compiler instead
+    let sysroot = query(&gcc_path, "-print-sysroot")
+        .filter(|sysroot| !sysroot.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            let name = gcc_path.file_name()?.to_str()?;
+            let triple = name.trim_end_matches("gcc").trim_end_matches('-');
+            let dir = gcc_path.parent()?.parent()?.join(triple);
+            if dir.is_dir() {
+                Some(dir)
+            } else {
+                None
+            }
+        });
+
+    debug!(
+        "found system toolchain {} (gcc {})",
+        gcc_path.display(),
+        gcc_version
+    );
+
+    Some(SystemToolchain {
+        gcc_path,
+        gcc_version,
+        sysroot,
+    })
+}
+
+impl SystemToolchain {
+    /// Returns the C++ compiler installed next to the C compiler, if any.
+    pub fn gxx_path(&self) -> Option<PathBuf> {
+        let name = self.gcc_path.file_name()?.to_str()?;
+        let path = self.gcc_path
+            .with_file_name(format!("{}g++", name.trim_end_matches("gcc")));
+        if path.is_file() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+}
+
+/// A clang installed on the host, used together with the sysroot of a
+/// clang toolchain.
+#[derive(Clone)]
+pub struct Clang {
+    pub path: PathBuf,
+    pub version: String,
+}
+
+/// Looks for clang, either at the configured path or on the `PATH`.
+pub fn detect_clang(path: Option<&str>) -> Option<Clang> {
+    let path = find_program(path.unwrap_or("clang"))?;
+
+    let version = match query(&path, "-dumpversion") {
+        Some(version) => version,
+        None => {
+            warn!("could not query version of {}", path.display());
+            return None;
+        }
+    };
+
+    debug!("found clang {} ({})", path.display(), version);
+
+    Some(Clang { path, version })
+}
+
+/// Looks for a cross compiler for the target on the `PATH`, without
+/// running it.
+pub fn find_compiler(target: &str) -> Option<PathBuf> {
+    prefixes(target)
+        .iter()
+        .filter_map(|prefix| find_program(&format!("{}gcc", prefix)))
+        .next()
+}
+
+/// Returns the prefixes cross compilers for the target are commonly
+/// installed under, e.g. `aarch64-linux-gnu-` for `aarch64-unknown-linux-gnu`.
+fn prefixes(target: &str) -> Vec<String> {
+    let mut prefixes = vec![format!("{}-", target)];
+    let gnu_triple = triple::gnu_triple(target);
+    if gnu_triple != target {
+        prefixes.push(format!("{}-", gnu_triple));
+    }
+    prefixes
+}
+
+fn find_program(name: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        let path = PathBuf::from(name);
+        return if path.is_file() { Some(path) } else { None };
+    }
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    })
+}
+
+fn query(gcc_path: &Path, flag: &str) -> Option<String> {
+    let output = process::Command::new(gcc_path).arg(flag).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
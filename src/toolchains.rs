@@ -1,5 +1,9 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use directories::ProjectDirs;
 use failure::Error;
@@ -7,31 +11,91 @@ use heck::ShoutySnakeCase;
 use platforms;
 use semver::VersionReq;
 
-use cargo::{CargoPackage, CargoProject};
-use package::{PackageInstall, PackageManager};
+use cargo::CargoPackage;
+use config::UserConfig;
+use package::{self, PackageInstall, PackageManager};
+use registry::{Registry, ToolchainBase, ToolchainFeature, ToolchainKind};
+use system::{self, Clang, SystemToolchain};
 
 pub struct ToolchainManager {
     dirs: ProjectDirs,
     host: platforms::Platform,
     package_manager: PackageManager,
+    registry: Registry,
+    config: UserConfig,
+    system_toolchains: RefCell<HashMap<String, Option<SystemToolchain>>>,
+    clang: RefCell<Option<Option<Clang>>>,
 }
 
 pub struct ToolchainInfo {
-    pub gcc_version: &'static str,
+    /// Compiler backend of the toolchain, `gcc` or `clang`.
+    pub compiler: String,
+    pub version: String,
+    pub size: u64,
+    /// Whether the toolchain is a cross compiler installed on the system.
+    pub system: bool,
+}
+
+pub struct ToolchainFeatureInfo {
+    pub crate_name: String,
+    pub crate_version_req: String,
+    pub libc: Option<String>,
+    pub size: u64,
+    pub installed: bool,
+    pub env_vars: Vec<String>,
+}
+
+pub struct InstalledPackage {
+    pub target: String,
+    pub path: PathBuf,
+    /// Describes the registry entry the package was installed from, or
+    /// `None` if the current registry no longer references it.
+    pub description: Option<String>,
+}
+
+pub struct ImportedPackage {
+    pub target: String,
+    pub description: String,
+}
+
+/// The compilers of a toolchain base, and the flags needed to use them.
+struct Compilers {
+    cc: PathBuf,
+    cxx: Option<PathBuf>,
+    cflags: Option<OsString>,
+    cxxflags: Option<OsString>,
+    rustflags: Vec<OsString>,
+}
+
+/// A toolchain base usable on the host, either a package from the registry
+/// or a cross compiler installed on the system.
+enum Base<'a> {
+    Package(&'a ToolchainBase),
+    System(SystemToolchain),
 }
 
 impl ToolchainManager {
-    pub fn new(dirs: &ProjectDirs) -> ToolchainManager {
+    /// Creates a toolchain manager. With `offline` set, the registry is not
+    /// refreshed from the mirror.
+    pub fn new(dirs: &ProjectDirs, offline: bool) -> Result<ToolchainManager, Error> {
         let host = platforms::guess_current().expect("unknown toolchain host");
 
-        let package_manager =
-            PackageManager::new(TOOLCHAIN_MIRROR).expect("could not initialize package manager");
+        let config = UserConfig::load(dirs)?;
+        let package_manager = PackageManager::new(mirrors(&config))
+            .expect("could not initialize package manager");
+
+        let mut registry = Registry::load(dirs.cache_dir(), &package_manager, offline);
+        registry.merge_user_file(&dirs.config_dir().join("toolchains.toml"))?;
 
-        ToolchainManager {
+        Ok(ToolchainManager {
             dirs: dirs.clone(),
             host: host.clone(),
             package_manager,
-        }
+            registry,
+            config,
+            system_toolchains: RefCell::new(HashMap::new()),
+            clang: RefCell::new(None),
+        })
     }
 
     pub fn host(&self) -> &str {
@@ -39,19 +103,88 @@ impl ToolchainManager {
     }
 
     pub fn get_toolchain_info(&self, target: &str) -> Option<ToolchainInfo> {
-        self.find_toolchain_base(target).map(|base| ToolchainInfo {
-            gcc_version: base.gcc_version,
+        self.find_base(target).map(|base| match base {
+            Base::Package(base) if base.kind == ToolchainKind::Clang => ToolchainInfo {
+                compiler: "clang".to_owned(),
+                version: self.find_clang()
+                    .map(|clang| clang.version)
+                    .unwrap_or_else(|| "not found".to_owned()),
+                size: base.size,
+                system: false,
+            },
+            Base::Package(base) => ToolchainInfo {
+                compiler: "gcc".to_owned(),
+                version: base.gcc_version.clone(),
+                size: base.size,
+                system: false,
+            },
+            Base::System(system) => ToolchainInfo {
+                compiler: "gcc".to_owned(),
+                version: system.gcc_version,
+                size: 0,
+                system: true,
+            },
         })
     }
 
+    pub fn get_available_targets(&self) -> Vec<String> {
+        let mut targets: Vec<String> = self.registry
+            .toolchains
+            .iter()
+            .filter(|t| self.is_usable_on_host(t))
+            .map(|t| t.target_platform_triple.clone())
+            .collect();
+
+        // system compilers, whether configured or found on the PATH
+        let mut system_targets: Vec<&str> = self.config
+            .toolchain
+            .keys()
+            .map(|target| target.as_str())
+            .chain(
+                platforms::ALL_PLATFORMS
+                    .iter()
+                    .map(|platform| platform.target_triple)
+                    .filter(|target| system::find_compiler(target).is_some()),
+            )
+            .collect();
+        system_targets.sort();
+        system_targets.dedup();
+
+        for target in system_targets {
+            if !targets.iter().any(|t| t == target) && self.find_base(target).is_some() {
+                targets.push(target.to_owned());
+            }
+        }
+
+        targets
+    }
+
+    pub fn get_toolchain_features(&self, target: &str) -> Vec<ToolchainFeatureInfo> {
+        self.registry
+            .features
+            .iter()
+            .filter(|t| t.target_platform_triple == target)
+            .map(|feature| ToolchainFeatureInfo {
+                crate_name: feature.crate_name.clone(),
+                crate_version_req: feature.crate_version_req.clone(),
+                libc: feature.libc.clone(),
+                size: feature.size,
+                installed: self.get_toolchain_feature_path(feature).exists(),
+                env_vars: feature.env_vars.keys().cloned().collect(),
+            })
+            .collect()
+    }
+
     pub fn is_toolchain_base_available(&self, target: &str) -> bool {
-        self.find_toolchain_base(target).is_some()
+        self.find_base(target).is_some()
     }
 
     pub fn is_toolchain_base_installed(&self, target: &str) -> bool {
-        self.find_toolchain_base(target)
-            .map(|base| self.get_toolchain_base_path(base).exists())
-            .unwrap_or(false)
+        match self.find_base(target) {
+            Some(Base::Package(base)) => self.get_toolchain_base_path(base).exists(),
+            Some(Base::System(_)) => true,
+            None => false,
+        }
     }
 
     pub fn is_toolchain_feature_available(&self, target: &str, cargo_pkg: &CargoPackage) -> bool {
@@ -69,7 +202,7 @@ impl ToolchainManager {
             .ok_or_else(|| format_err!("no toolchain available for target {}", target))?;
         let path = self.get_toolchain_base_path(&base);
         self.package_manager
-            .install(base.path, base.size, base.checksum, path)
+            .install(&base.path, base.size, &base.checksum, path)
     }
 
     pub fn start_toolchain_feature_installation(
@@ -81,67 +214,447 @@ impl ToolchainManager {
             .ok_or_else(|| format_err!("toolchain feature not available for target {}", target))?;
         let path = self.get_toolchain_feature_path(&feature);
         self.package_manager
-            .install(feature.path, feature.size, feature.checksum, path)
+            .install(&feature.path, feature.size, &feature.checksum, path)
+    }
+
+    pub fn start_named_toolchain_feature_installation(
+        &self,
+        target: &str,
+        crate_name: &str,
+        crate_version_req: &str,
+    ) -> Result<PackageInstall, Error> {
+        let feature = self.find_named_toolchain_feature(target, crate_name, crate_version_req)
+            .ok_or_else(|| format_err!("toolchain feature not available for target {}", target))?;
+        let path = self.get_toolchain_feature_path(feature);
+        self.package_manager
+            .install(&feature.path, feature.size, &feature.checksum, path)
+    }
+
+    pub fn get_named_toolchain_feature_environment(
+        &self,
+        target: &str,
+        crate_name: &str,
+        crate_version_req: &str,
+    ) -> Result<Vec<(String, OsString)>, Error> {
+        let feature = self.find_named_toolchain_feature(target, crate_name, crate_version_req)
+            .ok_or_else(|| format_err!("toolchain feature not available for target {}", target))?;
+        Ok(self.get_toolchain_feature_environment(feature))
+    }
+
+    /// Looks up the registry entry matching the checksum of a local archive
+    /// and prepares its installation. Returns `None` as the installation if
+    /// the package is already installed.
+    pub fn start_package_import(
+        &self,
+        archive: &Path,
+    ) -> Result<(ImportedPackage, Option<PackageInstall>), Error> {
+        let checksum = package::checksum(archive)?;
+
+        let (target, description, path) = if let Some(base) = self.registry
+            .toolchains
+            .iter()
+            .find(|t| self.is_usable_on_host(t) && t.checksum == checksum)
+        {
+            (
+                &base.target_platform_triple,
+                describe_base(base),
+                self.get_toolchain_base_path(base),
+            )
+        } else if let Some(feature) = self.registry.features.iter().find(|t| t.checksum == checksum)
+        {
+            (
+                &feature.target_platform_triple,
+                format!("{} {}", feature.crate_name, feature.crate_version_req),
+                self.get_toolchain_feature_path(feature),
+            )
+        } else {
+            bail!(
+                "{} (checksum {}) does not match any toolchain or support package for host {}.",
+                archive.display(),
+                checksum,
+                self.host.target_triple
+            );
+        };
+
+        let imported = ImportedPackage {
+            target: target.clone(),
+            description,
+        };
+        if path.exists() {
+            return Ok((imported, None));
+        }
+
+        let install = self.package_manager.import(archive, &checksum, path)?;
+        Ok((imported, Some(install)))
+    }
+
+    pub fn get_toolchain_compiler_path(&self, target: &str) -> Option<PathBuf> {
+        self.find_base(target).and_then(|base| match base {
+            Base::Package(base) if base.kind == ToolchainKind::Clang => {
+                self.find_clang().map(|clang| clang.path)
+            }
+            Base::Package(base) => Some(self.get_toolchain_gcc_path(base)),
+            Base::System(system) => Some(system.gcc_path),
+        })
+    }
+
+    pub fn get_installed_packages(&self) -> Result<Vec<InstalledPackage>, Error> {
+        let mut packages = vec![];
+
+        let root = self.get_targets_path();
+        if !root.exists() {
+            return Ok(packages);
+        }
+
+        for target_entry in fs::read_dir(&root)? {
+            let target_entry = target_entry?;
+            let target = target_entry.file_name().to_string_lossy().into_owned();
+
+            for kind in &["base", "feature"] {
+                let kind_path = target_entry.path().join(kind);
+                if !kind_path.is_dir() {
+                    continue;
+                }
+                for entry in fs::read_dir(&kind_path)? {
+                    let entry = entry?;
+                    let checksum = entry.file_name().to_string_lossy().into_owned();
+                    let description = if *kind == "base" {
+                        self.registry
+                            .toolchains
+                            .iter()
+                            .find(|t| t.target_platform_triple == target && t.checksum == checksum)
+                            .map(describe_base)
+                    } else {
+                        self.registry
+                            .features
+                            .iter()
+                            .find(|t| t.target_platform_triple == target && t.checksum == checksum)
+                            .map(|feature| {
+                                format!("{} {}", feature.crate_name, feature.crate_version_req)
+                            })
+                    };
+                    packages.push(InstalledPackage {
+                        target: target.clone(),
+                        path: entry.path(),
+                        description,
+                    });
+                }
+            }
+        }
+
+        packages.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(packages)
+    }
+
+    pub fn remove_toolchain(&self, target: &str) -> Result<bool, Error> {
+        let targets_path = self.get_targets_path();
+        if !targets_path.exists() {
+            return Ok(false);
+        }
+        // only remove what is actually installed, so that arbitrary paths
+        // given as a target can never escape the cache
+        for entry in fs::read_dir(&targets_path)? {
+            let entry = entry?;
+            if entry.file_name() == *target && entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
     pub fn get_toolchain_environment(
         &self,
         target: &str,
-        project: &CargoProject,
+        packages: &[CargoPackage],
+        unscoped: bool,
     ) -> Result<impl IntoIterator<Item = (String, OsString)>, Error> {
-        let base = self.find_toolchain_base(target)
+        let base = self.find_base(target)
             .ok_or_else(|| format_err!("no toolchain available for target {}", target))?;
-        let path = self.get_toolchain_base_path(&base);
 
-        let gcc_path = path.join("bin").join(format!("{}-gcc", target));
-        let gcc_include_path = path.join("lib")
-            .join("gcc")
-            .join(target)
-            .join(base.gcc_version)
-            .join("include");
-        let gcc_include_fixed_path = path.join("lib")
-            .join("gcc")
-            .join(target)
-            .join(base.gcc_version)
-            .join("include-fixed");
-
-        let mut cflags = OsString::from("");
-        cflags.push(" -I ");
-        cflags.push(&gcc_include_path);
-        cflags.push(" -I ");
-        cflags.push(&gcc_include_fixed_path);
+        let tools: Vec<(&str, PathBuf)> = BINUTILS
+            .iter()
+            .filter_map(|&(tool, var)| Some((var, self.get_toolchain_tool_path(&base, tool)?)))
+            .collect();
+
+        let compilers = self.get_toolchain_compilers(target, base)?;
+
+        let mut compiler_vars = vec![("CC", compilers.cc.clone().into_os_string())];
+        if let Some(cxx) = compilers.cxx {
+            compiler_vars.push(("CXX", cxx.into_os_string()));
+        }
+        for (var, path) in tools {
+            compiler_vars.push((var, path.into_os_string()));
+        }
+        if let Some(cflags) = compilers.cflags {
+            compiler_vars.push(("CFLAGS", cflags));
+        }
+        if let Some(cxxflags) = compilers.cxxflags {
+            compiler_vars.push(("CXXFLAGS", cxxflags));
+        }
 
         let mut envs = vec![
             ("CARGO_TARGET_DIR".into(), "target/cross".into()),
             (
                 format!("CARGO_TARGET_{}_LINKER", target.to_shouty_snake_case()),
-                gcc_path.clone().into_os_string(),
+                compilers.cc.into_os_string(),
+            ),
+            (
+                "HOST_CC".into(),
+                env::var_os("HOST_CC").unwrap_or_else(|| "cc".into()),
             ),
-            ("TARGET_CC".into(), gcc_path.clone().into_os_string()),
-            ("TARGET_CFLAGS".into(), cflags),
-            ("CHOST".into(), target.into()),
         ];
+        // cargo builds only get variables scoped to the target, so that build
+        // scripts and proc-macros compiled for the host keep using the host
+        // compiler
+        for (var, value) in compiler_vars {
+            if unscoped && UNSCOPED_VARS.contains(&var) {
+                envs.push((var.to_owned(), value.clone()));
+            }
+            envs.push((format!("{}_{}", var, target.replace('-', "_")), value));
+        }
 
-        for cargo_pkg in project.packages.iter() {
+        if !compilers.rustflags.is_empty() {
+            envs.push((
+                format!("CARGO_TARGET_{}_RUSTFLAGS", target.to_shouty_snake_case()),
+                join_flags(compilers.rustflags),
+            ));
+        }
+
+        for cargo_pkg in packages.iter() {
             if let Some(feature) = self.find_toolchain_feature(target, &cargo_pkg) {
-                let feature_path = self.get_toolchain_feature_path(&feature);
-                for (k, v) in feature.env_vars {
-                    envs.push((
-                        (*k).into(),
-                        v.replace("{CARGO_CROSS_FEAT_PATH}", &feature_path.to_string_lossy())
-                            .into(),
-                    ));
-                }
+                envs.extend(self.get_toolchain_feature_environment(feature));
             }
         }
 
         Ok(envs)
     }
 
+    fn get_toolchain_compilers(&self, target: &str, base: Base) -> Result<Compilers, Error> {
+        let compilers = match base {
+            Base::Package(base) if base.kind == ToolchainKind::Clang => {
+                let clang = self.find_clang().ok_or_else(|| {
+                    format_err!(
+                        "Could not find clang, which the toolchain for target {} requires.",
+                        target
+                    )
+                })?;
+                let clangxx = fs::canonicalize(&clang.path)
+                    .ok()
+                    .and_then(|path| Some(path.parent()?.join("clang++")))
+                    .filter(|path| path.is_file());
+                let sysroot = self.get_toolchain_sysroot_path(base);
+
+                let mut cflags =
+                    OsString::from(format!("--target={} --sysroot=", base.gnu_triple()));
+                cflags.push(&sysroot);
+
+                // rustc drives the link through clang, which needs the same
+                // target and sysroot, and is told to use lld
+                let mut sysroot_flag = OsString::from("-C link-arg=--sysroot=");
+                sysroot_flag.push(&sysroot);
+                let rustflags = vec![
+                    format!("-C link-arg=--target={}", base.gnu_triple()).into(),
+                    "-C link-arg=-fuse-ld=lld".into(),
+                    sysroot_flag,
+                ];
+
+                Compilers {
+                    cc: clang.path,
+                    cxx: clangxx,
+                    cflags: Some(cflags.clone()),
+                    cxxflags: Some(cflags),
+                    rustflags,
+                }
+            }
+            Base::Package(base) => {
+                let path = self.get_toolchain_base_path(base);
+
+                let gcc_path = self.get_toolchain_gcc_path(base);
+                let gxx_path = path.join(format!("{}g++", base.tool_prefix()));
+                let gcc_include_path = path.join("lib")
+                    .join("gcc")
+                    .join(base.gnu_triple())
+                    .join(&base.gcc_version)
+                    .join("include");
+                let gcc_include_fixed_path = path.join("lib")
+                    .join("gcc")
+                    .join(base.gnu_triple())
+                    .join(&base.gcc_version)
+                    .join("include-fixed");
+                let cxx_include_path = path.join(base.gnu_triple())
+                    .join("include")
+                    .join("c++")
+                    .join(&base.gcc_version);
+
+                let mut cflags = OsString::from("");
+                cflags.push(" -I ");
+                cflags.push(&gcc_include_path);
+                cflags.push(" -I ");
+                cflags.push(&gcc_include_fixed_path);
+
+                // the C++ headers have to come first, as they use
+                // #include_next to reach the C headers
+                let mut cxxflags = OsString::from("");
+                cxxflags.push(" -I ");
+                cxxflags.push(&cxx_include_path);
+                cxxflags.push(" -I ");
+                cxxflags.push(cxx_include_path.join(base.gnu_triple()));
+                cxxflags.push(" -I ");
+                cxxflags.push(cxx_include_path.join("backward"));
+                cxxflags.push(&cflags);
+
+                // make libstdc++ available to rustc when linking crates
+                // that bundle C++ code
+                let rustflags = ["lib64", "lib"]
+                    .iter()
+                    .map(|lib| path.join(base.gnu_triple()).join(lib))
+                    .filter(|dir| {
+                        dir.join("libstdc++.a").exists() || dir.join("libstdc++.so").exists()
+                    })
+                    .map(|dir| {
+                        let mut flag = OsString::from("-L native=");
+                        flag.push(dir);
+                        flag
+                    })
+                    .collect();
+
+                Compilers {
+                    cc: gcc_path,
+                    cxx: if gxx_path.is_file() { Some(gxx_path) } else { None },
+                    cflags: Some(cflags),
+                    cxxflags: Some(cxxflags),
+                    rustflags,
+                }
+            }
+            // system compilers know their own include and library paths
+            Base::System(system) => Compilers {
+                cxx: system.gxx_path(),
+                cc: system.gcc_path,
+                cflags: None,
+                cxxflags: None,
+                rustflags: vec![],
+            },
+        };
+        Ok(compilers)
+    }
+
+    pub fn get_toolchain_runner_environment(
+        &self,
+        target: &str,
+        runner: Option<&str>,
+    ) -> Result<Option<(String, OsString)>, Error> {
+        let base = self.find_base(target)
+            .ok_or_else(|| format_err!("no toolchain available for target {}", target))?;
+
+        let runner = match runner {
+            Some(runner) => runner.to_owned(),
+            None if self.is_foreign_target(target) => format!("qemu-{}", qemu_arch(target)),
+            None => return Ok(None),
+        };
+
+        let sysroot = match base {
+            Base::Package(base) => Some(self.get_toolchain_sysroot_path(base)),
+            Base::System(system) => system.sysroot,
+        };
+
+        // `-L` is qemu's option for the library prefix, other runners (e.g.
+        // wine or an ssh wrapper) would not understand it
+        let is_qemu = runner
+            .split_whitespace()
+            .next()
+            .and_then(|program| Path::new(program).file_name())
+            .and_then(|name| name.to_str())
+            .map(|name| name.starts_with("qemu-"))
+            .unwrap_or(false);
+
+        let mut value = OsString::from(runner);
+        if let Some(sysroot) = sysroot.filter(|_| is_qemu) {
+            value.push(" -L ");
+            value.push(sysroot);
+        }
+
+        Ok(Some((
+            format!("CARGO_TARGET_{}_RUNNER", target.to_shouty_snake_case()),
+            value,
+        )))
+    }
+
+    fn is_foreign_target(&self, target: &str) -> bool {
+        match platforms::find(target) {
+            Some(platform) => {
+                platform.target_arch != self.host.target_arch
+                    || platform.target_os != self.host.target_os
+            }
+            None => true,
+        }
+    }
+
+    /// Resolves the toolchain base for the target. A compiler configured
+    /// for the target takes precedence over the registry, which in turn
+    /// takes precedence over compilers found on the `PATH`.
+    fn find_base(&self, target: &str) -> Option<Base<'_>> {
+        let prefix = self.config
+            .toolchain
+            .get(target)
+            .and_then(|config| config.prefix.as_ref());
+        if prefix.is_none() {
+            if let Some(base) = self.find_toolchain_base(target) {
+                return Some(Base::Package(base));
+            }
+        }
+
+        self.system_toolchains
+            .borrow_mut()
+            .entry(target.to_owned())
+            .or_insert_with(|| system::detect(target, prefix.map(|prefix| prefix.as_str())))
+            .clone()
+            .map(Base::System)
+    }
+
+    /// Returns the path of a binutils tool (e.g. `ar`) of the toolchain, if
+    /// the toolchain provides it.
+    fn get_toolchain_tool_path(&self, base: &Base, tool: &str) -> Option<PathBuf> {
+        let path = match *base {
+            Base::Package(base) if base.kind == ToolchainKind::Clang => {
+                // resolve versioned symlinks such as /usr/bin/clang-16, to
+                // find the llvm tools installed next to clang
+                let clang = fs::canonicalize(self.find_clang()?.path).ok()?;
+                clang.parent()?.join(format!("llvm-{}", tool))
+            }
+            Base::Package(base) => self.get_toolchain_base_path(base)
+                .join(format!("{}{}", base.tool_prefix(), tool)),
+            Base::System(ref system) => {
+                let name = system.gcc_path.file_name()?.to_str()?;
+                let prefix = name.trim_end_matches("gcc");
+                system.gcc_path.with_file_name(format!("{}{}", prefix, tool))
+            }
+        };
+        if path.is_file() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
     fn find_toolchain_base(&self, target: &str) -> Option<&ToolchainBase> {
-        TOOLCHAINS_BASE.iter().find(|t| {
-            t.target_platform_triple == target && t.host_platform_triple == self.host.target_triple
-        })
+        self.registry
+            .toolchains
+            .iter()
+            .find(|t| t.target_platform_triple == target && self.is_usable_on_host(t))
+    }
+
+    /// Clang toolchains only contain a sysroot, and can be used on any host.
+    fn is_usable_on_host(&self, base: &ToolchainBase) -> bool {
+        base.kind == ToolchainKind::Clang || base.host_platform_triple == self.host.target_triple
+    }
+
+    fn find_clang(&self) -> Option<Clang> {
+        self.clang
+            .borrow_mut()
+            .get_or_insert_with(|| system::detect_clang(self.config.clang.as_deref()))
+            .clone()
     }
 
     fn find_toolchain_feature(
@@ -149,32 +662,83 @@ impl ToolchainManager {
         target: &str,
         cargo_pkg: &CargoPackage,
     ) -> Option<&ToolchainFeature> {
-        TOOLCHAIN_FEATURES
+        self.registry
+            .features
             .iter()
             .filter(|t| t.target_platform_triple == target && t.crate_name == cargo_pkg.name)
             .find(|t| {
                 let vreq =
-                    VersionReq::parse(t.crate_version_req).expect("failed to parse version req");
+                    VersionReq::parse(&t.crate_version_req).expect("failed to parse version req");
                 vreq.matches(&cargo_pkg.version)
             })
     }
 
+    fn find_named_toolchain_feature(
+        &self,
+        target: &str,
+        crate_name: &str,
+        crate_version_req: &str,
+    ) -> Option<&ToolchainFeature> {
+        self.registry.features.iter().find(|t| {
+            t.target_platform_triple == target
+                && t.crate_name == crate_name
+                && t.crate_version_req == crate_version_req
+        })
+    }
+
+    fn get_toolchain_feature_environment(
+        &self,
+        feature: &ToolchainFeature,
+    ) -> Vec<(String, OsString)> {
+        let feature_path = self.get_toolchain_feature_path(feature);
+        feature
+            .env_vars
+            .iter()
+            .map(|(k, v)| {
+                (
+                    k.clone(),
+                    v.replace("{CARGO_CROSS_FEAT_PATH}", &feature_path.to_string_lossy())
+                        .into(),
+                )
+            })
+            .collect()
+    }
+
+    fn get_targets_path(&self) -> PathBuf {
+        self.dirs.cache_dir().join("target")
+    }
+
     fn get_toolchain_base_path(&self, base: &ToolchainBase) -> PathBuf {
         let mut dir = self.dirs.cache_dir().to_path_buf();
         dir.extend(&[
             "target",
-            base.target_platform_triple,
+            &base.target_platform_triple,
             "base",
             &base.checksum,
         ]);
         dir
     }
 
+    fn get_toolchain_gcc_path(&self, base: &ToolchainBase) -> PathBuf {
+        self.get_toolchain_base_path(base)
+            .join(format!("{}gcc", base.tool_prefix()))
+    }
+
+    fn get_toolchain_sysroot_path(&self, base: &ToolchainBase) -> PathBuf {
+        match base.kind {
+            ToolchainKind::Gcc => self.get_toolchain_base_path(base)
+                .join(base.gnu_triple())
+                .join("sysroot"),
+            // the package of a clang toolchain is the sysroot itself
+            ToolchainKind::Clang => self.get_toolchain_base_path(base),
+        }
+    }
+
     fn get_toolchain_feature_path(&self, feature: &ToolchainFeature) -> PathBuf {
         let mut dir = self.dirs.cache_dir().to_path_buf();
         dir.extend(&[
             "target",
-            feature.target_platform_triple,
+            &feature.target_platform_triple,
             "feature",
             &feature.checksum,
         ]);
@@ -182,59 +746,69 @@ impl ToolchainManager {
     }
 }
 
-struct ToolchainBase {
-    target_platform_triple: &'static str,
-    host_platform_triple: &'static str,
-    gcc_version: &'static str,
-    path: &'static str,
-    checksum: &'static str,
-    size: u64,
+/// Binutils exported for the target, together with their variable names.
+static BINUTILS: &[(&str, &str)] = &[
+    ("ar", "AR"),
+    ("ranlib", "RANLIB"),
+    ("strip", "STRIP"),
+    ("objcopy", "OBJCOPY"),
+    ("nm", "NM"),
+];
+
+/// Variables also exported without the target suffix for build systems
+/// other than cargo (e.g. make or cmake), which only read these.
+static UNSCOPED_VARS: &[&str] = &["CC", "CXX", "AR", "RANLIB", "CFLAGS", "CXXFLAGS"];
+
+fn join_flags(flags: Vec<OsString>) -> OsString {
+    let mut joined = OsString::new();
+    for flag in flags {
+        if !joined.is_empty() {
+            joined.push(" ");
+        }
+        joined.push(flag);
+    }
+    joined
 }
 
-static TOOLCHAIN_MIRROR: &str = "https://d3ojaw7tkwhzj5.cloudfront.net";
+fn describe_base(base: &ToolchainBase) -> String {
+    match base.kind {
+        ToolchainKind::Gcc => format!("toolchain (gcc {})", base.gcc_version),
+        ToolchainKind::Clang => "toolchain (clang sysroot)".to_owned(),
+    }
+}
 
-static TOOLCHAINS_BASE: &[ToolchainBase] = &[ToolchainBase {
-    host_platform_triple: "x86_64-apple-darwin",
-    target_platform_triple: "x86_64-unknown-linux-gnu",
-    gcc_version: "4.8.5",
-    path: "target/x86_64-unknown-linux-gnu/base-x86_64-apple-darwin-4de47685.tar.xz",
-    size: 26366476,
-    checksum: "4de476857501db4748be7e3df4b42c52a3772ad6",
-}];
-
-struct ToolchainFeature {
-    target_platform_triple: &'static str,
-    crate_name: &'static str,
-    crate_version_req: &'static str,
-    path: &'static str,
-    size: u64,
-    checksum: &'static str,
-    env_vars: &'static [(&'static str, &'static str)],
+fn qemu_arch(target: &str) -> &str {
+    let arch = target.split('-').next().unwrap_or(target);
+    match arch {
+        "i386" | "i586" | "i686" => "i386",
+        "powerpc" => "ppc",
+        "powerpc64" => "ppc64",
+        "powerpc64le" => "ppc64le",
+        "riscv64gc" => "riscv64",
+        arch if arch.starts_with("arm") => "arm",
+        arch if arch.starts_with("thumb") => "arm",
+        arch => arch,
+    }
 }
 
-static TOOLCHAIN_FEATURES: &[ToolchainFeature] = &[
-    ToolchainFeature {
-        target_platform_triple: "x86_64-unknown-linux-gnu",
-        crate_name: "openssl-sys",
-        crate_version_req: "^0.9",
-        path: "target/x86_64-unknown-linux-gnu/feat-openssl-1.0.2p-1c466e90.tar.xz",
-        size: 1437664,
-        checksum: "1c466e9072d5c4c96e63b484d64b63a37290054f",
-        env_vars: &[
-            ("OPENSSL_DIR", "{CARGO_CROSS_FEAT_PATH}"),
-            ("OPENSSL_STATIC", "1"),
-        ],
-    },
-    ToolchainFeature {
-        target_platform_triple: "x86_64-unknown-linux-gnu",
-        crate_name: "libsqlite3-sys",
-        crate_version_req: "^0.9",
-        path: "target/x86_64-unknown-linux-gnu/feat-sqlite-3.24.0-3c3476b3.tar.xz",
-        size: 532560,
-        checksum: "3c3476b32a284fb5f6096e4ad025d4786508e118",
-        env_vars: &[
-            ("SQLITE3_INCLUDE_DIR", "{CARGO_CROSS_FEAT_PATH}/include"),
-            ("SQLITE3_LIB_DIR", "{CARGO_CROSS_FEAT_PATH}/lib"),
-        ],
-    },
-];
+/// Returns the mirrors to try in order, taken from the `CARGO_CROSS_MIRROR`
+/// variable (comma-separated), the user configuration or the default mirror.
+fn mirrors(config: &UserConfig) -> Vec<String> {
+    if let Some(value) = env::var_os("CARGO_CROSS_MIRROR") {
+        let mirrors: Vec<String> = value
+            .to_string_lossy()
+            .split(',')
+            .map(|mirror| mirror.trim().to_owned())
+            .filter(|mirror| !mirror.is_empty())
+            .collect();
+        if !mirrors.is_empty() {
+            return mirrors;
+        }
+    }
+    if !config.mirrors.is_empty() {
+        return config.mirrors.clone();
+    }
+    vec![TOOLCHAIN_MIRROR.to_owned()]
+}
+
+static TOOLCHAIN_MIRROR: &str = "https://d3ojaw7tkwhzj5.cloudfront.net";
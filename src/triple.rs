@@ -0,0 +1,59 @@
+/// Maps a Rust target triple to the GNU triple that cross compilers for the
+/// target are commonly named after, e.g. `arm-linux-gnueabihf` for
+/// `armv7-unknown-linux-gnueabihf` or `x86_64-w64-mingw32` for
+/// `x86_64-pc-windows-gnu`. Triples without a known mapping are returned
+/// unchanged.
+pub fn gnu_triple(target: &str) -> String {
+    let parts: Vec<&str> = target.split('-').collect();
+    match parts[..] {
+        [arch, "pc", "windows", "gnu"] => format!("{}-w64-mingw32", gnu_arch(arch)),
+        [arch, "unknown", "linux", env] => format!("{}-linux-{}", gnu_arch(arch), env),
+        _ => target.to_owned(),
+    }
+}
+
+fn gnu_arch(arch: &str) -> &str {
+    match arch {
+        "riscv64gc" => "riscv64",
+        arch if arch.starts_with("arm") || arch.starts_with("thumb") => "arm",
+        arch => arch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::gnu_triple;
+
+    #[test]
+    fn maps_linux_targets() {
+        assert_eq!(gnu_triple("x86_64-unknown-linux-gnu"), "x86_64-linux-gnu");
+        assert_eq!(gnu_triple("aarch64-unknown-linux-gnu"), "aarch64-linux-gnu");
+        assert_eq!(gnu_triple("x86_64-unknown-linux-musl"), "x86_64-linux-musl");
+        assert_eq!(gnu_triple("riscv64gc-unknown-linux-gnu"), "riscv64-linux-gnu");
+    }
+
+    #[test]
+    fn maps_arm_variants_to_arm() {
+        assert_eq!(
+            gnu_triple("armv7-unknown-linux-gnueabihf"),
+            "arm-linux-gnueabihf"
+        );
+        assert_eq!(gnu_triple("arm-unknown-linux-gnueabi"), "arm-linux-gnueabi");
+        assert_eq!(
+            gnu_triple("thumbv7neon-unknown-linux-gnueabihf"),
+            "arm-linux-gnueabihf"
+        );
+    }
+
+    #[test]
+    fn maps_windows_gnu_to_mingw() {
+        assert_eq!(gnu_triple("x86_64-pc-windows-gnu"), "x86_64-w64-mingw32");
+        assert_eq!(gnu_triple("i686-pc-windows-gnu"), "i686-w64-mingw32");
+    }
+
+    #[test]
+    fn keeps_unknown_triples() {
+        assert_eq!(gnu_triple("x86_64-apple-darwin"), "x86_64-apple-darwin");
+        assert_eq!(gnu_triple("wasm32-unknown-unknown"), "wasm32-unknown-unknown");
+    }
+}
@@ -0,0 +1,18 @@
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+
+/// Computes the total size of all files below `path`, without following
+/// symbolic links.
+pub fn disk_usage(path: &Path) -> Result<u64, Error> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += disk_usage(&entry?.path())?;
+    }
+    Ok(total)
+}
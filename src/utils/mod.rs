@@ -1,2 +1,3 @@
+pub mod du;
 pub mod hasher;
 pub mod progress;
@@ -4,6 +4,9 @@ pub trait ProgressObserver {
     fn progress(&mut self, delta: u64);
     fn complete(&mut self);
 
+    /// Called when a transfer is abandoned and started over.
+    fn restart(&mut self) {}
+
     fn observe_read<R: Read>(self, r: R) -> ReadProgress<Self, R>
     where
         Self: Sized,
@@ -15,6 +18,20 @@ pub trait ProgressObserver {
     }
 }
 
+impl<P: ProgressObserver> ProgressObserver for &mut P {
+    fn progress(&mut self, delta: u64) {
+        (**self).progress(delta);
+    }
+
+    fn complete(&mut self) {
+        (**self).complete();
+    }
+
+    fn restart(&mut self) {
+        (**self).restart();
+    }
+}
+
 pub struct ReadProgress<P, R> {
     observer: P,
     inner: R,
@@ -11,7 +11,7 @@ use cargo_toml_builder::prelude::*;
 use heck::SnakeCase;
 use tempfile::TempDir;
 
-fn compile_test(crate_name: &str, crate_version: &str, target: &str, main: &str) {
+fn project(crate_name: &str, crate_version: &str, main: &str) -> TempDir {
     let project_dir = TempDir::new().unwrap();
 
     let toml = CargoToml::builder()
@@ -31,6 +31,12 @@ fn compile_test(crate_name: &str, crate_version: &str, target: &str, main: &str)
     fs::create_dir(project_dir.as_ref().join("src")).unwrap();
     fs::write(project_dir.as_ref().join("src/main.rs"), main).unwrap();
 
+    project_dir
+}
+
+fn compile_test(crate_name: &str, crate_version: &str, target: &str, main: &str) {
+    let project_dir = project(crate_name, crate_version, main);
+
     let mut cmd = Command::main_binary().unwrap();
     cmd.current_dir(&project_dir);
     cmd.args(&["cross", "build", "--target", target]);
@@ -38,6 +44,16 @@ fn compile_test(crate_name: &str, crate_version: &str, target: &str, main: &str)
     cmd.assert().success();
 }
 
+fn check_test(crate_name: &str, crate_version: &str, target: &str, main: &str) {
+    let project_dir = project(crate_name, crate_version, main);
+
+    let mut cmd = Command::main_binary().unwrap();
+    cmd.current_dir(&project_dir);
+    cmd.args(&["cross", "check", "--target", target]);
+
+    cmd.assert().success();
+}
+
 #[test]
 fn openssl_sys_0_9_35_for_x86_64_unknown_linux_gnu() {
     compile_test(
@@ -102,3 +118,13 @@ fn brotli_sys_0_3_2_for_x86_64_unknown_linux_gnu() {
         "unsafe { ::brotli_sys::BrotliEncoderVersion(); }",
     );
 }
+
+#[test]
+fn check_openssl_sys_0_9_35_for_x86_64_unknown_linux_gnu() {
+    check_test(
+        "openssl-sys",
+        "0.9.35",
+        "x86_64-unknown-linux-gnu",
+        "::openssl_sys::init();",
+    );
+}